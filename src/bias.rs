@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::APP_DIR,
+    hid::DS4State,
+    motion::{SensorClock, GYRO_RES_PER_DEG_S},
+};
+
+const FILE_NAME: &str = "gyro_bias.toml";
+
+/// Settings of measuring the gyroscope's bias while the controller is still
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stillness {
+    /// Rotation in degrees per second, apart from the bias, below which the controller may be
+    /// still
+    pub max_rotation: f32,
+    /// Standard deviation of the rotation in degrees per second below which it's still
+    pub max_deviation: f32,
+    /// Difference of the acceleration from 1 g below which it may be still
+    pub max_acceleration: f32,
+    /// How long it's still for each measurement
+    pub time: Duration,
+}
+
+impl Default for Stillness {
+    fn default() -> Self {
+        Self {
+            max_rotation: 10.0,
+            max_deviation: 0.5,
+            max_acceleration: 0.05,
+            time: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Sums of the rotation read while the controller may be still
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Window {
+    time: Duration,
+    count: u32,
+    sum: [f64; 3],
+    squares: [f64; 3],
+}
+
+/// Measures the rotation the gyroscope reads while the controller is still, which drifts with
+/// its temperature, and takes it off the readings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimator {
+    settings: Stillness,
+    /// Rotation read while still, in degrees per second
+    bias: [f32; 3],
+    clock: SensorClock,
+    window: Window,
+}
+
+impl Estimator {
+    /// Estimator starting from a `bias` measured before
+    pub fn new(settings: &Stillness, bias: [f32; 3]) -> Self {
+        Self {
+            settings: settings.clone(),
+            bias,
+            clock: SensorClock::new(),
+            window: Window::default(),
+        }
+    }
+
+    /// Rotation the gyroscope reads while still, in degrees per second
+    pub fn bias(&self) -> [f32; 3] {
+        self.bias
+    }
+
+    /// Take in the calibrated motion of `state`, returning the bias when it's measured anew
+    pub fn update(&mut self, state: &DS4State) -> Option<[f32; 3]> {
+        let time = self.clock.tick(state);
+
+        let settings = &self.settings;
+        let rotation = state.motion.rotation();
+        let [x, y, z] = state.motion.acceleration();
+        let g = (x * x + y * y + z * z).sqrt();
+        let turning = rotation
+            .iter()
+            .zip(self.bias)
+            .any(|(rate, bias)| (rate - bias).abs() >= settings.max_rotation);
+        if turning || (g - 1.0).abs() >= settings.max_acceleration {
+            self.window = Window::default();
+            return None;
+        }
+        let window = &mut self.window;
+        window.time += time;
+        window.count += 1;
+        for (i, rate) in rotation.iter().enumerate() {
+            window.sum[i] += *rate as f64;
+            window.squares[i] += (*rate as f64).powi(2);
+        }
+        if window.time < settings.time {
+            return None;
+        }
+
+        let window = std::mem::take(&mut self.window);
+        let count = window.count as f64;
+        let mean = window.sum.map(|sum| sum / count);
+        let still = (0..3).all(|i| {
+            let variance = (window.squares[i] / count - mean[i].powi(2)).max(0.0);
+            variance.sqrt() < settings.max_deviation as f64
+        });
+        if !still {
+            return None;
+        }
+        self.bias = mean.map(|mean| mean as f32);
+        Some(self.bias)
+    }
+
+    /// Take the bias off the calibrated motion of `state`
+    pub fn apply(&self, state: &mut DS4State) {
+        for (rate, bias) in state.motion.gyro.iter_mut().zip(self.bias) {
+            *rate -= (bias * GYRO_RES_PER_DEG_S as f32).round() as i32;
+        }
+    }
+}
+
+/// Gyroscope bias of every controller measured so far, by serial number, in degrees per second
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Biases(pub BTreeMap<String, [f32; 3]>);
+
+impl Biases {
+    /// `$XDG_STATE_HOME/ds4linux/gyro_bias.toml`, or `~/.local/state/ds4linux/gyro_bias.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })?;
+        Some(state_home.join(APP_DIR).join(FILE_NAME))
+    }
+
+    /// Biases saved at `path`, none when there's no file yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read gyro biases {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Could not parse gyro biases {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("Could not write gyro biases {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{
+        testing::{self, assert_near},
+        ACCEL_RES_PER_G,
+    };
+
+    /// State `report` reports in, lying flat & turning at `rotation` degrees per second
+    fn resting(report: u32, rotation: [f32; 3]) -> DS4State {
+        let mut state = testing::report(report);
+        state.motion.gyro = rotation.map(|r| (r * GYRO_RES_PER_DEG_S as f32).round() as i32);
+        state.motion.accel = [0, ACCEL_RES_PER_G, 0];
+        state
+    }
+
+    #[test]
+    fn bias_is_measured_while_still() {
+        let mut estimator = Estimator::new(&Stillness::default(), [0.0; 3]);
+        let drift = [0.8, -1.2, 0.3];
+        let measured: Vec<_> = (0..=500)
+            .filter_map(|report| {
+                // a little noise around the drift
+                let noise = if report % 2 == 0 { 0.2 } else { -0.2 };
+                estimator.update(&resting(report, drift.map(|d| d + noise)))
+            })
+            .collect();
+        assert_eq!(measured.len(), 2);
+        assert_near(estimator.bias(), drift, 0.01);
+
+        let mut state = resting(501, [10.8, -1.2, 0.3]);
+        estimator.apply(&mut state);
+        assert_near(state.motion.rotation(), [10.0, 0.0, 0.0], 0.01);
+    }
+
+    #[test]
+    fn moving_or_shaking_isnt_still() {
+        let mut estimator = Estimator::new(&Stillness::default(), [0.5; 3]);
+        for report in 0..500 {
+            let turning = if report % 100 == 0 { 40.0 } else { 0.0 };
+            assert_eq!(
+                estimator.update(&resting(report, [turning, 0.0, 0.0])),
+                None
+            );
+        }
+        for report in 500..1000 {
+            let shaky = if report % 2 == 0 { 3.0 } else { -3.0 };
+            assert_eq!(estimator.update(&resting(report, [shaky; 3])), None);
+        }
+        assert_eq!(estimator.bias(), [0.5; 3]);
+    }
+
+    #[test]
+    fn biases_are_saved_by_serial() {
+        let dir = std::env::temp_dir().join(format!("ds4linux-bias-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        assert_eq!(Biases::load(&path).unwrap(), Biases::default());
+
+        let mut biases = Biases::default();
+        biases
+            .0
+            .insert("a4:15:66:12:34:56".to_string(), [0.5, -1.25, 0.0]);
+        biases.save(&path).unwrap();
+        let loaded = Biases::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), biases);
+    }
+}
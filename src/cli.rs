@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+pub const USAGE: &str = "Usage: ds4linux [--config <file>] [--tui]
+       ds4linux config check [<file>]
+       ds4linux config import <ds4windows profile> [--name <name>]
+       ds4linux config dump [--resolved] [<file>]
+       ds4linux calibrate
+
+Options:
+    --config <file>  Read configuration from <file> instead of ~/.config/ds4linux/config.toml
+    --tui            Show the live curve editor for the analog sticks
+    --help           Print this message
+
+Commands:
+    config check     Validate a config file, defaulting to the one the driver would use
+    config import    Convert a DS4Windows XML profile and print it as a config profile named after
+                     the file, or <name>
+    config dump      Print a config file as the driver reads it, with --resolved every profile
+                     includes the settings it inherits through `extends`
+    calibrate        Measure the gyroscope's bias of the controller lying still on a table, which
+                     the driver otherwise measures whenever it's put down";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run {
+        config: Option<PathBuf>,
+        tui: bool,
+    },
+    CheckConfig {
+        file: Option<PathBuf>,
+    },
+    ImportConfig {
+        file: PathBuf,
+        name: Option<String>,
+    },
+    DumpConfig {
+        file: Option<PathBuf>,
+        resolved: bool,
+    },
+    Calibrate,
+    Help,
+}
+
+impl Command {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut config = None;
+        let mut tui = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "config" if config.is_none() && !tui => return Self::parse_config(args),
+                "calibrate" if config.is_none() && !tui => {
+                    if let Some(arg) = args.next() {
+                        bail!("Unexpected argument `{}`\n\n{}", arg, USAGE);
+                    }
+                    return Ok(Command::Calibrate);
+                }
+                "--config" | "-c" => {
+                    let path = args.next().context("--config needs a file argument")?;
+                    config = Some(path.into());
+                }
+                "--tui" => tui = true,
+                "--help" | "-h" => return Ok(Command::Help),
+                _ => match arg.strip_prefix("--config=") {
+                    Some(path) => config = Some(path.into()),
+                    None => bail!("Unknown argument `{}`\n\n{}", arg, USAGE),
+                },
+            }
+        }
+        Ok(Command::Run { config, tui })
+    }
+
+    fn parse_config(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        match args.next().as_deref() {
+            Some("check") => {
+                let file = args.next().map(PathBuf::from);
+                if let Some(arg) = args.next() {
+                    bail!("Unexpected argument `{}`\n\n{}", arg, USAGE);
+                }
+                Ok(Command::CheckConfig { file })
+            }
+            Some("import") => {
+                let mut file = None;
+                let mut name = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--name" => name = Some(args.next().context("--name needs a value")?),
+                        _ if file.is_none() => file = Some(PathBuf::from(arg)),
+                        _ => bail!("Unexpected argument `{}`\n\n{}", arg, USAGE),
+                    }
+                }
+                let file =
+                    file.with_context(|| format!("Missing profile to import\n\n{}", USAGE))?;
+                Ok(Command::ImportConfig { file, name })
+            }
+            Some("dump") => {
+                let mut file = None;
+                let mut resolved = false;
+                for arg in args {
+                    match arg.as_str() {
+                        "--resolved" => resolved = true,
+                        _ if file.is_none() => file = Some(PathBuf::from(arg)),
+                        _ => bail!("Unexpected argument `{}`\n\n{}", arg, USAGE),
+                    }
+                }
+                Ok(Command::DumpConfig { file, resolved })
+            }
+            Some(other) => bail!("Unknown config command `{}`\n\n{}", other, USAGE),
+            None => bail!("Missing config command\n\n{}", USAGE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_run_with_defaults() {
+        assert_eq!(
+            parse(&[]).unwrap(),
+            Command::Run {
+                config: None,
+                tui: false
+            }
+        );
+    }
+
+    #[test]
+    fn config_path_is_read() {
+        let expected = Command::Run {
+            config: Some(PathBuf::from("my.toml")),
+            tui: true,
+        };
+        assert_eq!(parse(&["--config", "my.toml", "--tui"]).unwrap(), expected);
+        assert_eq!(parse(&["--tui", "--config=my.toml"]).unwrap(), expected);
+        assert!(parse(&["--config"]).is_err());
+    }
+
+    #[test]
+    fn config_check_takes_optional_file() {
+        assert_eq!(
+            parse(&["config", "check", "my.toml"]).unwrap(),
+            Command::CheckConfig {
+                file: Some(PathBuf::from("my.toml"))
+            }
+        );
+        assert_eq!(
+            parse(&["config", "check"]).unwrap(),
+            Command::CheckConfig { file: None }
+        );
+        assert!(parse(&["config", "chekc"]).is_err());
+        assert!(parse(&["config", "check", "a.toml", "b.toml"]).is_err());
+    }
+
+    #[test]
+    fn config_import_needs_file() {
+        assert_eq!(
+            parse(&["config", "import", "Game.xml", "--name", "game"]).unwrap(),
+            Command::ImportConfig {
+                file: PathBuf::from("Game.xml"),
+                name: Some("game".to_string())
+            }
+        );
+        assert!(parse(&["config", "import"]).is_err());
+        assert!(parse(&["config", "import", "a.xml", "b.xml"]).is_err());
+    }
+
+    #[test]
+    fn config_dump_takes_optional_file() {
+        assert_eq!(
+            parse(&["config", "dump", "--resolved"]).unwrap(),
+            Command::DumpConfig {
+                file: None,
+                resolved: true
+            }
+        );
+        assert_eq!(
+            parse(&["config", "dump", "my.toml"]).unwrap(),
+            Command::DumpConfig {
+                file: Some(PathBuf::from("my.toml")),
+                resolved: false
+            }
+        );
+    }
+
+    #[test]
+    fn calibrate_takes_no_arguments() {
+        assert_eq!(parse(&["calibrate"]).unwrap(), Command::Calibrate);
+        assert!(parse(&["calibrate", "now"]).is_err());
+    }
+
+    #[test]
+    fn unknown_argument_is_rejected() {
+        assert!(parse(&["--tiu"]).is_err());
+    }
+}
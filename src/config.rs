@@ -0,0 +1,269 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inherit,
+    preset::Preset,
+    process::AutoSwitch,
+    profile::Profile,
+    switch::Chord,
+    validate::{self, Diagnostic},
+};
+
+pub(crate) const APP_DIR: &str = "ds4linux";
+const FILE_NAME: &str = "config.toml";
+pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+/// Contents of the configuration file. Anything left out of the file keeps the built in default,
+/// which is how the driver behaved before it was configurable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Profile active on start, the first one by name when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Controller the virtual device presents itself as, which profiles take their bindings from
+    pub emulate: Preset,
+    /// Chord cycling through the profiles, in order of their names
+    pub switch: Chord,
+    /// Switching profiles for the running applications
+    pub auto_switch: AutoSwitch,
+    pub devices: Devices,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Virtual devices created besides the gamepad, like those of the kernel driver
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Devices {
+    /// Multi-touch touchpad reporting the fingers on the controller's touchpad
+    pub touchpad: bool,
+    /// Motion sensor device reporting the controller's accelerometer & gyroscope
+    pub motion: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_profile: None,
+            emulate: Preset::default(),
+            switch: Chord::default(),
+            auto_switch: AutoSwitch::default(),
+            devices: Devices::default(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Profile::default())]),
+        }
+    }
+}
+
+/// Every problem found in a config file
+#[derive(Debug)]
+pub struct InvalidConfig {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid config {}", self.path.display())?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n{}:{}", self.path.display(), diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+impl Config {
+    /// Name of the profile active on start
+    pub fn default_profile_name(&self) -> &str {
+        self.default_profile
+            .as_deref()
+            .or_else(|| self.profiles.keys().next().map(String::as_str))
+            .unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Read and validate config at `path`, failing with [`InvalidConfig`] if it has any problems
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config {}", path.display()))?;
+        Self::parse(&content).map_err(|diagnostics| {
+            InvalidConfig {
+                path: path.to_owned(),
+                diagnostics,
+            }
+            .into()
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Vec<Diagnostic>> {
+        validate::check(content)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml()?)
+            .with_context(|| format!("Could not write config {}", path.display()))
+    }
+
+    /// The config as it's written to a file, where profiles only hold the settings they change
+    /// of the profile they extend, or else of the `emulate` preset's
+    pub fn to_toml(&self) -> Result<String> {
+        let mut table = toml::Table::try_from(self)?;
+        let preset = toml::Table::try_from(self.emulate.profile())?;
+        if let Some(toml::Value::Table(profiles)) = table.get_mut("profiles") {
+            for (name, profile) in &self.profiles {
+                let base = match profile.extends.as_ref().and_then(|b| self.profiles.get(b)) {
+                    Some(base) => inherit::inherited(toml::Table::try_from(base)?),
+                    // the preset's bindings are merged in again on loading, see `preset::apply`
+                    None if self.emulate != Preset::Generic => preset.clone(),
+                    None => continue,
+                };
+                let changed = inherit::diff(&base, &toml::Table::try_from(profile)?);
+                profiles.insert(name.clone(), toml::Value::Table(changed));
+            }
+        }
+        Ok(toml::to_string_pretty(&table)?)
+    }
+
+    /// The config with every profile holding all of its settings, as the driver uses them
+    pub fn to_resolved_toml(&self) -> Result<String> {
+        let mut resolved = self.clone();
+        for profile in resolved.profiles.values_mut() {
+            profile.extends = None;
+        }
+        Ok(toml::to_string_pretty(&resolved)?)
+    }
+
+    /// Where the user's config file lives: `$XDG_CONFIG_HOME/ds4linux/config.toml`, with
+    /// `$XDG_CONFIG_HOME` defaulting to `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join(APP_DIR).join(FILE_NAME))
+    }
+
+    /// First existing config file of the user's one and the system wide ones in `$XDG_CONFIG_DIRS`
+    pub fn find() -> Option<PathBuf> {
+        let config_dirs = env::var("XDG_CONFIG_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/etc/xdg".to_string());
+        Self::default_path()
+            .into_iter()
+            .chain(
+                config_dirs
+                    .split(':')
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| Path::new(dir).join(APP_DIR).join(FILE_NAME)),
+            )
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_is_default_behaviour() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn example_config_is_default_behaviour() {
+        let example = include_str!("../config.example.toml");
+        assert_eq!(Config::parse(example).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(Config::parse("[profiles.default.buttons]\nsqaure = \"BTN_WEST\"").is_err());
+    }
+
+    #[test]
+    fn first_profile_is_default_unless_set() {
+        let source = "[profiles.steam]\n[profiles.retroarch]\n";
+        assert_eq!(
+            Config::parse(source).unwrap().default_profile_name(),
+            "retroarch"
+        );
+        let source = format!("default_profile = \"steam\"\n{}", source);
+        assert_eq!(
+            Config::parse(&source).unwrap().default_profile_name(),
+            "steam"
+        );
+    }
+
+    #[test]
+    fn saving_keeps_inheritance() {
+        let source = "[profiles.base.right_stick]\nflat = 20\n[profiles.game]\nextends = \"base\"\n[profiles.game.left_stick.x]\ndeadzone = 5\n";
+        let config = Config::parse(source).unwrap();
+        assert_eq!(config.profiles["game"].right_stick.flat, 20);
+
+        let saved = config.to_toml().unwrap();
+        let game: toml::Table = toml::from_str::<toml::Table>(&saved).unwrap()["profiles"]["game"]
+            .as_table()
+            .unwrap()
+            .clone();
+        assert_eq!(
+            game,
+            toml::from_str("extends = \"base\"\n[left_stick.x]\ndeadzone = 5").unwrap()
+        );
+        assert_eq!(Config::parse(&saved).unwrap(), config);
+        assert!(!config.to_resolved_toml().unwrap().contains("extends"));
+
+        // match rules aren't inherited, so the same ones as the base's are saved too
+        let source = "[profiles.base.match]\nprocess = [\"game\"]\n\
+                      [profiles.game]\nextends = \"base\"\n[profiles.game.match]\nprocess = [\"game\"]\n";
+        let config = Config::parse(source).unwrap();
+        assert_eq!(Config::parse(&config.to_toml().unwrap()).unwrap(), config);
+    }
+
+    #[test]
+    fn saving_leaves_out_preset_bindings() {
+        let source = "emulate = \"xbox360\"\n[profiles.default.map]\ncross = \"KEY_SPACE\"\n";
+        let config = Config::parse(source).unwrap();
+        let saved = config.to_toml().unwrap();
+        let default: toml::Table = toml::from_str::<toml::Table>(&saved).unwrap()["profiles"]
+            ["default"]
+            .as_table()
+            .unwrap()
+            .clone();
+        assert_eq!(
+            default,
+            toml::from_str("[map]\ncross = \"KEY_SPACE\"").unwrap()
+        );
+        assert_eq!(Config::parse(&saved).unwrap(), config);
+
+        // emulating something else drops the xbox360 bindings
+        let generic = Config::parse(&saved.replace("xbox360", "generic")).unwrap();
+        let map = &generic.profiles["default"].map;
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn invalid_config_lists_problems_with_location() {
+        let err = InvalidConfig {
+            path: "config.toml".into(),
+            diagnostics: Config::parse("[profiles.default.right_stick.y]\ndeadzone = 128")
+                .unwrap_err(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Invalid config config.toml\nconfig.toml:2:12: profiles.default.right_stick.y.deadzone: 128 is out of range, expected 0 to 127"
+        );
+    }
+}
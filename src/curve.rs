@@ -1,3 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// Response curve of an analog axis. Either one of the curve functions in this module, a
+/// piecewise linear curve through user defined control points (see [`points`]), or a cubic
+/// Bézier curve (see [`bezier`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    InQuad,
+    OutQuad,
+    Cubic,
+    InOutQuad,
+    Custom,
+    Points(Vec<[f32; 2]>),
+    Bezier([f32; 4]),
+}
+
+impl Curve {
+    /// Return curved value of `x`, see the function of the same name for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ds4linux::curve::Curve;
+    ///
+    /// assert_eq!(Curve::Linear.apply(75, 100), 75.0);
+    /// assert_eq!(Curve::InQuad.apply(75, 100), 62.5);
+    /// assert_eq!(Curve::Points(vec![[0.5, 0.25], [1.0, 1.0]]).apply(75, 100), 62.5);
+    /// ```
+    pub fn apply(&self, x: u8, max: u8) -> f32 {
+        match self {
+            Curve::Linear => linear(x, max) as f32,
+            Curve::InQuad => in_quad(x, max),
+            Curve::OutQuad => out_quad(x, max),
+            Curve::Cubic => cubic(x, max),
+            Curve::InOutQuad => in_out_quad(x, max),
+            Curve::Custom => custom(x, max),
+            Curve::Points(p) => points(x, max, p),
+            Curve::Bezier(c) => bezier(x, max, c),
+        }
+    }
+
+    /// Return curved `offset` from center, both as a fraction of full deflection from `-1.0` to
+    /// `1.0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ds4linux::curve::Curve;
+    ///
+    /// assert_eq!(Curve::Linear.normalized(0.0), 0.0);
+    /// assert!((Curve::InQuad.normalized(-0.5) + 0.25).abs() < 0.01);
+    /// assert_eq!(Curve::Linear.normalized(0.001), 0.001);
+    /// ```
+    pub fn normalized(&self, offset: f32) -> f32 {
+        let offset = offset.clamp(-1.0, 1.0);
+        if offset == 0.0 {
+            return 0.0;
+        }
+        // every curve but the custom one mirrors its positive half, so it's evaluated there
+        let m = offset.abs();
+        let magnitude = match self {
+            Curve::Linear => m,
+            Curve::InQuad => m * m,
+            Curve::OutQuad => m * (2.0 - m),
+            Curve::Cubic => m * m * m,
+            Curve::InOutQuad if m < 0.5 => 2.0 * m * m,
+            Curve::InOutQuad => -2.0 * m * m + 4.0 * m - 1.0,
+            Curve::Points(p) => interpolate(p, m),
+            Curve::Bezier(c) => bezier_magnitude(m, c),
+            Curve::Custom => {
+                // in between the values of the table
+                let middle = u8::MAX as f32 / 2.0;
+                let x = middle + offset * middle;
+                let (low, high) = (x.floor(), x.ceil());
+                let value = custom(low as u8, u8::MAX)
+                    + (custom(high as u8, u8::MAX) - custom(low as u8, u8::MAX)) * (x - low);
+                return ((value - middle) / middle).clamp(-1.0, 1.0);
+            }
+        };
+        magnitude.clamp(0.0, 1.0).copysign(offset)
+    }
+
+    /// Sample the positive half of the curve into `n` evenly spaced control points, making it
+    /// editable as a [`Curve::Points`] curve.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ds4linux::curve::Curve;
+    ///
+    /// assert_eq!(Curve::Linear.to_points(3), vec![[0.0, 0.0], [0.5, 0.5], [1.0, 1.0]]);
+    /// ```
+    pub fn to_points(&self, n: usize) -> Vec<[f32; 2]> {
+        if let Curve::Points(p) = self {
+            return p.clone();
+        }
+        let middle = u8::MAX as f32 / 2.0;
+        (0..n)
+            .map(|i| {
+                let input = i as f32 / (n - 1) as f32;
+                // sample at the rounded raw value, but store the exact input for even spacing
+                let x = (middle + input * middle).round() as u8;
+                let output = ((self.apply(x, u8::MAX) - middle) / middle).clamp(0.0, 1.0);
+                [input, (output * 100.0).round() / 100.0]
+            })
+            .collect()
+    }
+}
+
 pub fn linear(x: u8, _: u8) -> u8 {
     x
 }
@@ -27,7 +139,7 @@ pub fn in_quad(x: u8, max: u8) -> f32 {
         ((normalized * normalized) * middle) + middle
     } else {
         let normalized = x as f32 / middle;
-        (1.0 - (1.0 - normalized) * (1.0 - normalized)) * middle as f32
+        (1.0 - (1.0 - normalized) * (1.0 - normalized)) * middle
     }
 }
 
@@ -86,7 +198,7 @@ pub fn cubic(x: u8, max: u8) -> f32 {
         ((normalized * normalized * normalized) * middle) + middle
     } else {
         let normalized = (x as f32 / middle) - 1.0;
-        (normalized * normalized * normalized + 1.0) * middle as f32
+        (normalized * normalized * normalized + 1.0) * middle
     }
 }
 
@@ -115,12 +227,109 @@ pub fn in_out_quad(x: u8, max: u8) -> f32 {
     }
 }
 
+/// Return piecewise linear 'curve' value of `x` going outwards from middle value of `min`/`max` boundary. `0` is the implicit min.
+///
+/// Each control point is `[input, output]`, both normalized so `0.0` is the middle value and `1.0` is
+/// full deflection. Points are expected in ascending input order, with an implicit first point at `[0.0, 0.0]`.
+/// The negative half of the axis mirrors the positive half. Without any points the curve is linear.
+///
+/// # Examples
+///
+/// ```
+/// use ds4linux::curve::points;
+///
+/// let points_ = [[0.5, 0.25], [1.0, 1.0]];
+/// assert_eq!(points(75, 100, &points_), 62.5);
+/// assert_eq!(points(25, 100, &points_), 37.5);
+///
+/// // min & max & mid values remain the same
+/// assert_eq!(points(0, 100, &points_), 0.0);
+/// assert_eq!(points(100, 100, &points_), 100.0);
+/// assert_eq!(points(50, 100, &points_), 50.0);
+/// ```
+pub fn points(x: u8, max: u8, points: &[[f32; 2]]) -> f32 {
+    let middle = max as f32 / 2.0;
+    let offset = (x as f32 - middle) / middle;
+    let magnitude = interpolate(points, offset.abs());
+    middle + magnitude.copysign(offset) * middle
+}
+
+/// Return cubic Bézier 'curve' value of `x` going outwards from middle value of `min`/`max` boundary. `0` is the implicit min.
+///
+/// The curve runs from `[0.0, 0.0]` to `[1.0, 1.0]`, normalized like the control points of [`points`],
+/// and `control` holds its two inner control points as `[x1, y1, x2, y2]`, the way CSS easing functions
+/// and DS4Windows describe them. The negative half of the axis mirrors the positive half.
+///
+/// # Examples
+///
+/// ```
+/// use ds4linux::curve::bezier;
+///
+/// // straight control points make a linear curve
+/// assert_eq!(bezier(75, 100, &[0.25, 0.25, 0.75, 0.75]).round(), 75.0);
+/// assert!(bezier(75, 100, &[0.5, 0.0, 1.0, 0.5]) < 75.0);
+///
+/// // min & max & mid values remain the same
+/// assert_eq!(bezier(0, 100, &[0.5, 0.0, 1.0, 0.5]), 0.0);
+/// assert_eq!(bezier(100, 100, &[0.5, 0.0, 1.0, 0.5]), 100.0);
+/// assert_eq!(bezier(50, 100, &[0.5, 0.0, 1.0, 0.5]), 50.0);
+/// ```
+pub fn bezier(x: u8, max: u8, control: &[f32; 4]) -> f32 {
+    let middle = max as f32 / 2.0;
+    let offset = (x as f32 - middle) / middle;
+    middle + bezier_magnitude(offset.abs(), control).copysign(offset) * middle
+}
+
+/// Output of the Bézier curve through `control` at input `x` from `0.0` to `1.0`
+fn bezier_magnitude(x: f32, control: &[f32; 4]) -> f32 {
+    let [x1, y1, x2, y2] = *control;
+    let cubic = |t: f32, p1: f32, p2: f32| {
+        let u = 1.0 - t;
+        3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+    };
+    // x(t) only grows for control points within 0 and 1, so t can be found by bisection
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..24 {
+        let t = (low + high) / 2.0;
+        if cubic(t, x1, x2) < x {
+            low = t;
+        } else {
+            high = t;
+        }
+    }
+    // the ends are exact, so the full deflection & center values remain the same
+    if x == 0.0 || x == 1.0 {
+        x
+    } else {
+        cubic((low + high) / 2.0, y1, y2)
+    }
+}
+
+fn interpolate(points: &[[f32; 2]], x: f32) -> f32 {
+    if points.is_empty() {
+        return x;
+    }
+    let mut prev = [0.0, 0.0];
+    for &point in points {
+        if x <= point[0] {
+            let span = point[0] - prev[0];
+            if span <= 0.0 {
+                return point[1];
+            }
+            return prev[1] + (point[1] - prev[1]) * (x - prev[0]) / span;
+        }
+        prev = point;
+    }
+    prev[1]
+}
+
 /// Get customized curve from `CUSTOM_CURVE_VALS` table
 pub fn custom(x: u8, _: u8) -> f32 {
     CUSTOM_CURVE_VALS[x as usize]
 }
 
 /// Absolute hackiest way to create curved values. Why do this? Because I suuuuuck.
+#[allow(clippy::excessive_precision)]
 const CUSTOM_CURVE_VALS: [f32; 256] = [
     0.0,
     1.9921875,
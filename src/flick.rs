@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hid::{Button, DS4State, ANALOG_MAX};
+
+/// Flick stick settings of a profile: the right stick points the way to face in the game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlickStick {
+    pub enabled: bool,
+    /// Mouse motion which turns the game's camera around once
+    pub counts_per_360: f32,
+    /// Fraction of full deflection at which the stick is flicked
+    pub threshold: f32,
+    /// How long a flick takes to turn, `0` turning at once
+    pub flick_time_ms: u64,
+    /// Button turning around once, to check `counts_per_360` against the game
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibrate: Option<Button>,
+}
+
+impl Default for FlickStick {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            counts_per_360: 3600.0,
+            threshold: 0.9,
+            flick_time_ms: 100,
+            calibrate: None,
+        }
+    }
+}
+
+/// Turn spread over the flick time
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Turn {
+    start: Duration,
+    /// Degrees to turn, clockwise
+    angle: f32,
+    /// Degrees turned so far
+    done: f32,
+}
+
+/// Turns the right stick into mouse motion: flicking it turns to face where it points, and
+/// rotating it while held turns along
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flick {
+    settings: FlickStick,
+    /// Where the stick points while it's past the threshold
+    angle: Option<f32>,
+    turn: Option<Turn>,
+    /// Whether the calibration button was held
+    calibrating: bool,
+}
+
+impl Flick {
+    pub fn new(settings: &FlickStick) -> Self {
+        Self {
+            settings: settings.clone(),
+            angle: None,
+            turn: None,
+            calibrating: false,
+        }
+    }
+
+    /// Horizontal mouse motion for the right stick of `state` at `time`
+    pub fn update(&mut self, state: &DS4State, time: Duration) -> f32 {
+        let mut degrees = 0.0;
+        let middle = ANALOG_MAX as f32 / 2.0;
+        let x = (state.rsx as f32 - middle) / middle;
+        let y = (state.rsy as f32 - middle) / middle;
+        // up is straight ahead, and clockwise is turning right
+        let angle = x.atan2(-y).to_degrees();
+        if x.hypot(y) >= self.settings.threshold {
+            match self.angle {
+                None => degrees += self.start_turn(angle, time),
+                Some(last) => degrees += wrap(angle - last),
+            }
+            self.angle = Some(angle);
+        } else {
+            self.angle = None;
+        }
+
+        let calibrating = self
+            .settings
+            .calibrate
+            .is_some_and(|button| state.pressed(button));
+        if calibrating && !self.calibrating {
+            degrees += self.start_turn(360.0, time);
+        }
+        self.calibrating = calibrating;
+
+        if let Some(turn) = &mut self.turn {
+            let flick_time = Duration::from_millis(self.settings.flick_time_ms);
+            let progress = if flick_time.is_zero() {
+                1.0
+            } else {
+                (time.saturating_sub(turn.start).as_secs_f32() / flick_time.as_secs_f32()).min(1.0)
+            };
+            let step = turn.angle * progress - turn.done;
+            turn.done += step;
+            degrees += step;
+            if progress >= 1.0 {
+                self.turn = None;
+            }
+        }
+        degrees * self.settings.counts_per_360 / 360.0
+    }
+
+    /// Start turning by `angle` at `time`, returning what's left of the previous turn
+    fn start_turn(&mut self, angle: f32, time: Duration) -> f32 {
+        let rest = self.turn.map_or(0.0, |turn| turn.angle - turn.done);
+        self.turn = Some(Turn {
+            start: time,
+            angle,
+            done: 0.0,
+        });
+        rest
+    }
+}
+
+/// `degrees` within -180 to 180, the shortest way around
+fn wrap(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(rsx: u8, rsy: u8) -> DS4State {
+        DS4State {
+            rsx,
+            rsy,
+            ..DS4State::default()
+        }
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn flick() -> Flick {
+        Flick::new(&FlickStick {
+            enabled: true,
+            counts_per_360: 360.0,
+            ..FlickStick::default()
+        })
+    }
+
+    #[test]
+    fn flick_turns_to_the_stick_angle_over_the_flick_time() {
+        let mut flick = flick();
+        assert_eq!(flick.update(&stick(128, 128), ms(0)), 0.0);
+        let turned: f32 = [0, 50, 100, 150]
+            .map(|t| flick.update(&stick(255, 128), ms(10 + t)))
+            .iter()
+            .sum();
+        assert!((turned - 90.0).abs() < 1.0, "{}", turned);
+
+        // pointing back turns around
+        let mut flick = self::flick();
+        let first = flick.update(&stick(128, 255), ms(0));
+        let rest = flick.update(&stick(128, 255), ms(100));
+        assert!(first.abs() < 0.01, "{}", first);
+        assert!((rest.abs() - 180.0).abs() < 1.0, "{}", rest);
+    }
+
+    #[test]
+    fn rotating_the_held_stick_turns_along() {
+        let mut flick = Flick::new(&FlickStick {
+            counts_per_360: 720.0,
+            flick_time_ms: 0,
+            ..FlickStick::default()
+        });
+        let flicked = flick.update(&stick(128, 0), ms(0));
+        assert!(flicked.abs() < 2.0, "{}", flicked);
+        // a quarter turn to the left, at twice the counts per degree
+        let turned = flick.update(&stick(0, 128), ms(8));
+        assert!((turned + 180.0).abs() < 2.0, "{}", turned);
+        // letting go doesn't turn back
+        assert_eq!(flick.update(&stick(128, 128), ms(16)), 0.0);
+    }
+
+    #[test]
+    fn calibration_turns_around_once() {
+        let mut flick = Flick::new(&FlickStick {
+            counts_per_360: 1000.0,
+            calibrate: Some(Button::Ps),
+            ..FlickStick::default()
+        });
+        let mut state = stick(128, 128);
+        state.ps = true;
+        let turned: f32 = [0, 60, 120, 180]
+            .map(|t| flick.update(&state, ms(t)))
+            .iter()
+            .sum();
+        assert!((turned - 1000.0).abs() < 0.1, "{}", turned);
+    }
+
+    #[test]
+    fn angles_wrap_the_short_way() {
+        assert_eq!(wrap(270.0), -90.0);
+        assert_eq!(wrap(-200.0), 160.0);
+        assert_eq!(wrap(45.0), 45.0);
+    }
+}
@@ -0,0 +1,233 @@
+use std::time::Duration;
+
+use crate::motion::Motion;
+
+/// Rotation as a unit quaternion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Rotation by `degrees` around `axis`, which is of unit length
+    pub fn from_axis_angle(axis: [f32; 3], degrees: f32) -> Self {
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Quaternion {
+            w: cos,
+            x: axis[0] * sin,
+            y: axis[1] * sin,
+            z: axis[2] * sin,
+        }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Rotation by `other`, then by this one
+    pub fn mul(&self, other: &Quaternion) -> Self {
+        let (a, b) = (self, other);
+        Quaternion {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+
+    pub fn normalized(&self) -> Self {
+        let length = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        Quaternion {
+            w: self.w / length,
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+        }
+    }
+
+    /// Vector `v` rotated by this rotation
+    pub fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        let p = Quaternion {
+            w: 0.0,
+            x: v[0],
+            y: v[1],
+            z: v[2],
+        };
+        let r = self.mul(&p).mul(&self.conjugate());
+        [r.x, r.y, r.z]
+    }
+}
+
+/// Settings of the orientation estimate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fusion {
+    /// How fast the accelerometer corrects the drift of the gyroscope, in radians per second per
+    /// radian of error
+    pub gain: f32,
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self { gain: 1.0 }
+    }
+}
+
+/// Orientation of the controller estimated from its motion sensors with a Mahony filter: the
+/// gyroscope's rotation, corrected towards the gravity the accelerometer measures. The world's
+/// axes are those of the controller lying flat, y pointing up, so only turning it flat isn't
+/// corrected and drifts. The gyroscope's bias is expected to be taken off already, see
+/// [`crate::bias::Estimator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orientation {
+    settings: Fusion,
+    /// Rotation from the controller's axes to the world's
+    quaternion: Quaternion,
+}
+
+impl Orientation {
+    pub fn new(settings: &Fusion) -> Self {
+        Self {
+            settings: settings.clone(),
+            quaternion: Quaternion::IDENTITY,
+        }
+    }
+
+    /// Take in calibrated readings `motion` of the sensors, `time` after the previous ones
+    pub fn update(&mut self, motion: &Motion, time: Duration) {
+        let settings = &self.settings;
+        let rotation = motion.rotation();
+        let acceleration = motion.acceleration();
+        let g = length(acceleration);
+
+        // the gyroscope's axes are the controller's: pitch around x, yaw around y, roll around z
+        let mut omega = rotation.map(f32::to_radians);
+        // shaking adds to gravity, which then can't tell which way is up
+        if (g - 1.0).abs() < 0.5 {
+            let measured = acceleration.map(|a| a / g);
+            let estimated = self.up();
+            let error = cross(measured, estimated);
+            for (omega, error) in omega.iter_mut().zip(error) {
+                *omega += settings.gain * error;
+            }
+        }
+
+        let half = time.as_secs_f32() / 2.0;
+        let spin = self.quaternion.mul(&Quaternion {
+            w: 0.0,
+            x: omega[0],
+            y: omega[1],
+            z: omega[2],
+        });
+        let q = &self.quaternion;
+        self.quaternion = Quaternion {
+            w: q.w + spin.w * half,
+            x: q.x + spin.x * half,
+            y: q.y + spin.y * half,
+            z: q.z + spin.z * half,
+        }
+        .normalized();
+    }
+
+    /// Rotation from the controller's axes to the world's
+    pub fn quaternion(&self) -> Quaternion {
+        self.quaternion
+    }
+
+    /// Direction of gravity along the controller's axes, in g
+    pub fn gravity(&self) -> [f32; 3] {
+        self.up().map(|up| -up)
+    }
+
+    /// Up along the controller's axes
+    fn up(&self) -> [f32; 3] {
+        self.quaternion.conjugate().rotate([0.0, 1.0, 0.0])
+    }
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{testing::assert_near, ACCEL_RES_PER_G, GYRO_RES_PER_DEG_S};
+
+    /// Time between reports of the controller
+    const REPORT: Duration = Duration::from_millis(4);
+
+    /// Readings of `rotation` in degrees per second and `acceleration` in g
+    fn motion(rotation: [f32; 3], acceleration: [f32; 3]) -> Motion {
+        Motion {
+            gyro: rotation.map(|r| (r * GYRO_RES_PER_DEG_S as f32) as i32),
+            accel: acceleration.map(|a| (a * ACCEL_RES_PER_G as f32) as i32),
+        }
+    }
+
+    /// `orientation` after `seconds` of the same readings
+    fn run(orientation: &mut Orientation, seconds: f32, motion: Motion) {
+        let reports = (seconds / REPORT.as_secs_f32()).round() as usize;
+        for _ in 0..reports {
+            orientation.update(&motion, REPORT);
+        }
+    }
+
+    #[test]
+    fn quaternions_rotate_vectors() {
+        let quarter = Quaternion::from_axis_angle([0.0, 1.0, 0.0], 90.0);
+        assert_near(quarter.rotate([1.0, 0.0, 0.0]), [0.0, 0.0, -1.0], 1e-6);
+        let back = quarter.conjugate().mul(&quarter);
+        assert_near([back.x, back.y, back.z], [0.0; 3], 1e-6);
+    }
+
+    #[test]
+    fn turning_flat_follows_the_gyroscope() {
+        let mut orientation = Orientation::new(&Fusion::default());
+        run(
+            &mut orientation,
+            1.0,
+            motion([0.0, 90.0, 0.0], [0.0, 1.0, 0.0]),
+        );
+        let right = orientation.quaternion().rotate([1.0, 0.0, 0.0]);
+        assert_near(right, [0.0, 0.0, -1.0], 0.02);
+        assert_near(orientation.gravity(), [0.0, -1.0, 0.0], 0.01);
+    }
+
+    #[test]
+    fn gravity_corrects_the_tilt() {
+        let mut orientation = Orientation::new(&Fusion::default());
+        // lying on its left side, the gyroscope never having seen it turn
+        run(&mut orientation, 10.0, motion([0.0; 3], [1.0, 0.0, 0.0]));
+        assert_near(orientation.gravity(), [-1.0, 0.0, 0.0], 0.02);
+    }
+
+    #[test]
+    fn shaking_doesnt_tilt() {
+        let mut orientation = Orientation::new(&Fusion::default());
+        run(&mut orientation, 1.0, motion([0.0; 3], [2.5, 1.0, 0.0]));
+        assert_near(orientation.gravity(), [0.0, -1.0, 0.0], 1e-3);
+    }
+}
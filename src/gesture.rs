@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hid::{Touch, TOUCHPAD_HEIGHT, TOUCHPAD_WIDTH},
+    touchpad::{distance, Session},
+};
+
+/// Direction of a swipe, or the edge an edge swipe starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Touchpad gesture, which is bindable like a button that's pressed for one report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Gesture {
+    Swipe(Direction),
+    TwoFingerSwipe(Direction),
+    /// One finger swipe starting at an edge of the pad, away from it
+    EdgeSwipe(Direction),
+    /// Two fingers moving closer together
+    Pinch,
+    /// Two fingers moving apart
+    Spread,
+    /// One finger resting on the pad
+    LongPress,
+}
+
+impl Gesture {
+    /// Names of the gestures in config files
+    pub const NAMES: [(&'static str, Gesture); 15] = [
+        ("swipe_left", Gesture::Swipe(Direction::Left)),
+        ("swipe_right", Gesture::Swipe(Direction::Right)),
+        ("swipe_up", Gesture::Swipe(Direction::Up)),
+        ("swipe_down", Gesture::Swipe(Direction::Down)),
+        (
+            "two_finger_swipe_left",
+            Gesture::TwoFingerSwipe(Direction::Left),
+        ),
+        (
+            "two_finger_swipe_right",
+            Gesture::TwoFingerSwipe(Direction::Right),
+        ),
+        (
+            "two_finger_swipe_up",
+            Gesture::TwoFingerSwipe(Direction::Up),
+        ),
+        (
+            "two_finger_swipe_down",
+            Gesture::TwoFingerSwipe(Direction::Down),
+        ),
+        ("left_edge_swipe", Gesture::EdgeSwipe(Direction::Left)),
+        ("right_edge_swipe", Gesture::EdgeSwipe(Direction::Right)),
+        ("top_edge_swipe", Gesture::EdgeSwipe(Direction::Up)),
+        ("bottom_edge_swipe", Gesture::EdgeSwipe(Direction::Down)),
+        ("pinch", Gesture::Pinch),
+        ("spread", Gesture::Spread),
+        ("long_press", Gesture::LongPress),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let (name, _) = Gesture::NAMES
+            .iter()
+            .find(|(_, g)| g == self)
+            .expect("every gesture is named");
+        name
+    }
+}
+
+/// Gesture settings of a profile's touchpad
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gestures {
+    /// Shortest distance in touchpad units which is a swipe
+    pub swipe_distance: u16,
+    /// Longest a swipe lasts, from putting the first finger down until lifting the last
+    pub swipe_time_ms: u64,
+    /// Change of distance between two fingers in touchpad units which is a pinch or spread
+    pub pinch_distance: u16,
+    /// How long a finger rests on the pad for a long press
+    pub long_press_ms: u64,
+    /// Farthest a finger moves in touchpad units during a long press
+    pub long_press_distance: u16,
+    /// Fraction of the pad along each edge which edge swipes start from
+    pub edge_width: f32,
+}
+
+impl Default for Gestures {
+    fn default() -> Self {
+        Self {
+            swipe_distance: 400,
+            swipe_time_ms: 500,
+            pinch_distance: 300,
+            long_press_ms: 600,
+            long_press_distance: 60,
+            edge_width: 0.1,
+        }
+    }
+}
+
+/// Recognizes gestures in the touchpad fingers of consecutive reports. It only depends on the
+/// fingers and times it's given, so a trace of them is always recognized the same.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recognizer {
+    settings: Gestures,
+    session: Option<Session>,
+    /// Distance between two fingers of the session when both were first down, and when last
+    spread: Option<(f32, f32)>,
+    /// Whether a gesture of the session was recognized already, which ends it
+    done: bool,
+}
+
+impl Recognizer {
+    pub fn new(settings: &Gestures) -> Self {
+        Self {
+            settings: settings.clone(),
+            session: None,
+            spread: None,
+            done: false,
+        }
+    }
+
+    /// Gesture completed by fingers `touches` at `time`, if any. Swipes, pinches & spreads
+    /// complete when the last finger is lifted, long presses while the finger rests.
+    pub fn update(&mut self, time: Duration, touches: &[Option<Touch>; 2]) -> Option<Gesture> {
+        let down: Vec<Touch> = touches.iter().flatten().copied().collect();
+        let started = self.session.is_none();
+        if let Some(session) = Session::track(&mut self.session, &down, time) {
+            if self.done {
+                return None;
+            }
+            return self.finish(&session, time);
+        }
+        let session = self.session.as_ref()?;
+        if started {
+            self.spread = None;
+            self.done = false;
+        }
+        if let [a, b] = down[..] {
+            let distance = distance(&a, &b);
+            let first = self.spread.map_or(distance, |(first, _)| first);
+            self.spread = Some((first, distance));
+        }
+
+        let resting =
+            session.fingers == 1 && session.moved() <= self.settings.long_press_distance as f32;
+        let held = session.duration(time) >= Duration::from_millis(self.settings.long_press_ms);
+        if !self.done && resting && held {
+            self.done = true;
+            return Some(Gesture::LongPress);
+        }
+        None
+    }
+
+    fn finish(&self, session: &Session, time: Duration) -> Option<Gesture> {
+        let settings = &self.settings;
+        if let (2, Some((first, last))) = (session.fingers, self.spread) {
+            let change = last - first;
+            if change.abs() >= settings.pinch_distance as f32 {
+                return Some(if change < 0.0 {
+                    Gesture::Pinch
+                } else {
+                    Gesture::Spread
+                });
+            }
+        }
+        if session.duration(time) > Duration::from_millis(settings.swipe_time_ms) {
+            return None;
+        }
+        let count = session.paths.len() as f32;
+        let (dx, dy) = session
+            .paths
+            .iter()
+            .fold((0.0, 0.0), |(dx, dy), (origin, last)| {
+                (
+                    dx + (last.x as f32 - origin.x as f32) / count,
+                    dy + (last.y as f32 - origin.y as f32) / count,
+                )
+            });
+        if dx.abs().max(dy.abs()) < settings.swipe_distance as f32 {
+            return None;
+        }
+        let direction = match (dx.abs() >= dy.abs(), dx < 0.0, dy < 0.0) {
+            (true, true, _) => Direction::Left,
+            (true, false, _) => Direction::Right,
+            (false, _, true) => Direction::Up,
+            (false, _, false) => Direction::Down,
+        };
+        match session.fingers {
+            1 => {
+                let (origin, _) = session.paths[0];
+                let x = origin.x as f32 / TOUCHPAD_WIDTH as f32;
+                let y = origin.y as f32 / TOUCHPAD_HEIGHT as f32;
+                let edge = settings.edge_width;
+                // the edge a swipe starts from is the one it moves away from
+                let from_edge = match direction {
+                    Direction::Right => (x < edge).then_some(Direction::Left),
+                    Direction::Left => (x > 1.0 - edge).then_some(Direction::Right),
+                    Direction::Down => (y < edge).then_some(Direction::Up),
+                    Direction::Up => (y > 1.0 - edge).then_some(Direction::Down),
+                };
+                Some(from_edge.map_or(Gesture::Swipe(direction), Gesture::EdgeSwipe))
+            }
+            2 => Some(Gesture::TwoFingerSwipe(direction)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fingers on the pad, each given as `(id, x, y)`
+    type Fingers<'a> = &'a [(u8, u16, u16)];
+
+    /// Gestures recognized in `trace` of milliseconds & fingers
+    fn recognize(trace: &[(u64, Fingers)]) -> Vec<Gesture> {
+        let mut recognizer = Recognizer::new(&Gestures::default());
+        trace
+            .iter()
+            .filter_map(|(ms, fingers)| {
+                let mut touches = [None; 2];
+                for (slot, (id, x, y)) in touches.iter_mut().zip(fingers.iter()) {
+                    *slot = Some(Touch {
+                        id: *id,
+                        x: *x,
+                        y: *y,
+                    });
+                }
+                recognizer.update(Duration::from_millis(*ms), &touches)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gestures_are_named() {
+        for (name, gesture) in Gesture::NAMES {
+            assert_eq!(gesture.name(), name);
+        }
+    }
+
+    #[test]
+    fn one_finger_swipes() {
+        let swipe = recognize(&[
+            (0, &[(1, 1200, 500)]),
+            (100, &[(1, 900, 520)]),
+            (200, &[(1, 600, 540)]),
+            (204, &[]),
+        ]);
+        assert_eq!(swipe, [Gesture::Swipe(Direction::Left)]);
+
+        let short = recognize(&[(0, &[(1, 1200, 500)]), (100, &[(1, 1000, 500)]), (104, &[])]);
+        assert_eq!(short, []);
+
+        let slow = recognize(&[(0, &[(1, 500, 100)]), (900, &[(1, 500, 800)]), (904, &[])]);
+        assert_eq!(slow, []);
+    }
+
+    #[test]
+    fn swipes_from_an_edge() {
+        let edge = recognize(&[(0, &[(1, 50, 400)]), (150, &[(1, 700, 420)]), (154, &[])]);
+        assert_eq!(edge, [Gesture::EdgeSwipe(Direction::Left)]);
+
+        let bottom = recognize(&[(0, &[(1, 900, 930)]), (150, &[(1, 900, 300)]), (154, &[])]);
+        assert_eq!(bottom, [Gesture::EdgeSwipe(Direction::Down)]);
+    }
+
+    #[test]
+    fn two_finger_swipes() {
+        let swipe = recognize(&[
+            (0, &[(1, 600, 800)]),
+            (8, &[(1, 600, 800), (2, 900, 800)]),
+            (200, &[(1, 610, 300), (2, 905, 290)]),
+            (204, &[(2, 905, 290)]),
+            (208, &[]),
+        ]);
+        assert_eq!(swipe, [Gesture::TwoFingerSwipe(Direction::Up)]);
+    }
+
+    #[test]
+    fn pinch_and_spread() {
+        let pinch = recognize(&[
+            (0, &[(1, 300, 400), (2, 1600, 400)]),
+            (300, &[(1, 700, 450), (2, 1200, 450)]),
+            (304, &[]),
+        ]);
+        assert_eq!(pinch, [Gesture::Pinch]);
+
+        // spreading slowly, still a spread
+        let spread = recognize(&[
+            (0, &[(1, 800, 400), (2, 1000, 400)]),
+            (1000, &[(1, 400, 400), (2, 1400, 400)]),
+            (1004, &[]),
+        ]);
+        assert_eq!(spread, [Gesture::Spread]);
+    }
+
+    #[test]
+    fn long_press_completes_while_resting() {
+        let long = recognize(&[
+            (0, &[(1, 900, 400)]),
+            (300, &[(1, 910, 405)]),
+            (600, &[(1, 905, 400)]),
+            (900, &[(1, 905, 400)]),
+            (1000, &[]),
+        ]);
+        assert_eq!(long, [Gesture::LongPress]);
+
+        let moving = recognize(&[
+            (0, &[(1, 900, 400)]),
+            (300, &[(1, 1100, 400)]),
+            (700, &[(1, 1100, 400)]),
+            (800, &[]),
+        ]);
+        assert_eq!(moving, []);
+    }
+}
@@ -0,0 +1,372 @@
+use std::{collections::VecDeque, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    curve::Curve,
+    fusion::{Fusion, Orientation},
+    hid::{Button, DS4State},
+    motion::SensorClock,
+};
+
+/// What turning the controller is used for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Off,
+    /// Moves the mouse pointer, like aiming with a mouse
+    Mouse,
+    /// Deflects the right stick on top of where it's held
+    Stick,
+}
+
+/// Rotation of the controller which aims left & right
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Horizontal {
+    /// Turning it flat, like a steering column
+    #[default]
+    Yaw,
+    /// Leaning it to the side, like a steering wheel
+    Roll,
+    /// Both, turning it around its own up or leaning it, however it's held
+    Local,
+    /// Turning it around the world's up, however it's tilted, like turning the player around
+    Player,
+}
+
+/// Gyro aiming settings of a profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gyro {
+    pub mode: Mode,
+    pub horizontal: Horizontal,
+    /// Multiplier of the horizontal rotation, negative to invert it
+    pub sensitivity_x: f32,
+    /// Multiplier of the vertical rotation, negative to invert it
+    pub sensitivity_y: f32,
+    /// Response to the rotation speed up to `stick_speed`, beyond which it's linear
+    pub curve: Curve,
+    /// Pointer motion per degree the controller turns, in mouse mode
+    pub mouse_speed: f32,
+    /// Degrees per second the controller turns at which the stick is fully deflected, in stick
+    /// mode
+    pub stick_speed: f32,
+    /// Button which turns aiming off while held, to turn the controller back without moving
+    /// the aim, like lifting a mouse
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratchet: Option<Button>,
+    /// Only aim while a finger rests on the touchpad
+    pub on_touch: bool,
+    /// Rotation in degrees per second below which it's averaged over `smoothing_time_ms`,
+    /// hiding the shake of the hands. `0` turns it off.
+    pub smoothing_threshold: f32,
+    pub smoothing_time_ms: u64,
+    /// Rotation in degrees per second below which it's slowed down, the slower the more,
+    /// keeping the aim still when holding it still. `0` turns it off.
+    pub tightening_threshold: f32,
+}
+
+impl Default for Gyro {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Off,
+            horizontal: Horizontal::Yaw,
+            sensitivity_x: 1.0,
+            sensitivity_y: 1.0,
+            curve: Curve::Linear,
+            mouse_speed: 10.0,
+            stick_speed: 180.0,
+            ratchet: None,
+            on_touch: false,
+            smoothing_threshold: 0.0,
+            smoothing_time_ms: 125,
+            tightening_threshold: 0.0,
+        }
+    }
+}
+
+/// Turns the rotation of the controller into mouse motion or stick deflection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aim {
+    settings: Gyro,
+    clock: SensorClock,
+    /// Recent rotation, and how long each was for, for smoothing
+    history: VecDeque<(Duration, [f32; 2])>,
+    /// Which way is up, for aiming in player space
+    orientation: Orientation,
+}
+
+impl Aim {
+    pub fn new(settings: &Gyro) -> Self {
+        Self {
+            settings: settings.clone(),
+            clock: SensorClock::new(),
+            history: VecDeque::new(),
+            orientation: Orientation::new(&Fusion::default()),
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.settings.mode
+    }
+
+    /// Aim of the controller in `state`, right & down being positive: pointer motion in mouse
+    /// mode, offsets of the stick's x & y axes from `-1.0` to `1.0` in stick mode
+    pub fn update(&mut self, state: &DS4State) -> (f32, f32) {
+        let time = self.clock.tick(state);
+        self.orientation.update(&state.motion, time);
+
+        let ratchet = self.settings.ratchet;
+        let ratcheted = ratchet.is_some_and(|button| state.pressed(button));
+        let touched = state.touches.iter().any(Option::is_some);
+        if ratcheted || (self.settings.on_touch && !touched) {
+            self.history.clear();
+            return (0.0, 0.0);
+        }
+
+        let [pitch, yaw, roll] = state.motion.rotation();
+        // turning right & tilting down are negative around the controller's axes
+        let horizontal = match self.settings.horizontal {
+            Horizontal::Yaw => -yaw,
+            Horizontal::Roll => -roll,
+            Horizontal::Local => -(yaw + roll),
+            Horizontal::Player => {
+                let [x, y, z] = self.orientation.gravity().map(|g| -g);
+                -(pitch * x + yaw * y + roll * z)
+            }
+        };
+        let smoothed = self.smooth(time, [horizontal, -pitch]);
+        let [x, y] = self.tighten(smoothed);
+        let gain = self.gain(x.hypot(y));
+        let settings = &self.settings;
+        let (x, y) = (
+            x * gain * settings.sensitivity_x,
+            y * gain * settings.sensitivity_y,
+        );
+        match settings.mode {
+            Mode::Off => (0.0, 0.0),
+            Mode::Mouse => {
+                let degrees = time.as_secs_f32() * settings.mouse_speed;
+                (x * degrees, y * degrees)
+            }
+            Mode::Stick => {
+                let speed = settings.stick_speed;
+                ((x / speed).clamp(-1.0, 1.0), (y / speed).clamp(-1.0, 1.0))
+            }
+        }
+    }
+
+    /// `rate` lasting `time`, blended with its average over the smoothing time the slower it is
+    fn smooth(&mut self, time: Duration, rate: [f32; 2]) -> [f32; 2] {
+        let threshold = self.settings.smoothing_threshold;
+        if threshold <= 0.0 {
+            return rate;
+        }
+        self.history.push_front((time, rate));
+        let window = Duration::from_millis(self.settings.smoothing_time_ms);
+        let mut covered = Duration::ZERO;
+        let mut kept = 0;
+        for (time, _) in &self.history {
+            kept += 1;
+            covered += *time;
+            if covered >= window {
+                break;
+            }
+        }
+        self.history.truncate(kept);
+
+        let total = covered.as_secs_f32();
+        let average = if total > 0.0 {
+            self.history.iter().fold([0.0; 2], |sum, (time, rate)| {
+                let weight = time.as_secs_f32() / total;
+                [sum[0] + rate[0] * weight, sum[1] + rate[1] * weight]
+            })
+        } else {
+            rate
+        };
+        // from half the threshold on up, the rotation takes over from its average
+        let speed = rate[0].hypot(rate[1]);
+        let direct = ((speed - threshold / 2.0) / (threshold / 2.0)).clamp(0.0, 1.0);
+        [0, 1].map(|i| rate[i] * direct + average[i] * (1.0 - direct))
+    }
+
+    /// Multiplier of the rotation at `speed` in degrees per second from the curve, which spans
+    /// up to the stick speed
+    fn gain(&self, speed: f32) -> f32 {
+        let fraction = (speed / self.settings.stick_speed).min(1.0);
+        if fraction == 0.0 {
+            return 0.0;
+        }
+        self.settings.curve.normalized(fraction) / fraction
+    }
+
+    /// `rate` slowed down in proportion to how far below the tightening threshold it is
+    fn tighten(&self, rate: [f32; 2]) -> [f32; 2] {
+        let threshold = self.settings.tightening_threshold;
+        let speed = rate[0].hypot(rate[1]);
+        if threshold <= 0.0 || speed >= threshold {
+            return rate;
+        }
+        rate.map(|r| r * speed / threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{testing::sensor_time, ACCEL_RES_PER_G, GYRO_RES_PER_DEG_S};
+
+    /// Controller state `ms` of sensor time in, turning at `rotation` degrees per second
+    fn turning(ms: u32, rotation: [f32; 3]) -> DS4State {
+        let mut state = DS4State {
+            sensor_time: sensor_time(ms),
+            ..DS4State::default()
+        };
+        state.motion.gyro = rotation.map(|r| (r * GYRO_RES_PER_DEG_S as f32) as i32);
+        state
+    }
+
+    fn aim(settings: Gyro) -> Aim {
+        Aim::new(&Gyro {
+            mode: Mode::Mouse,
+            ..settings
+        })
+    }
+
+    #[test]
+    fn turning_moves_the_mouse_by_the_angle() {
+        let mut aim = aim(Gyro::default());
+        assert_eq!(aim.update(&turning(0, [0.0, -90.0, 0.0])), (0.0, 0.0));
+        // a quarter second turning right at 90 degrees per second, and up at 20
+        let (x, y) = aim.update(&turning(250, [20.0, -90.0, 0.0]));
+        assert!((x - 225.0).abs() < 0.1, "{}", x);
+        assert!((y + 50.0).abs() < 0.1, "{}", y);
+    }
+
+    #[test]
+    fn horizontal_axis_is_selectable() {
+        let leaning = [0.0, 0.0, -40.0];
+        let mut yaw = aim(Gyro::default());
+        yaw.update(&turning(0, leaning));
+        assert_eq!(yaw.update(&turning(100, leaning)).0, 0.0);
+
+        let mut roll = aim(Gyro {
+            horizontal: Horizontal::Roll,
+            sensitivity_x: -1.0,
+            ..Gyro::default()
+        });
+        roll.update(&turning(0, leaning));
+        let (x, _) = roll.update(&turning(100, leaning));
+        assert!((x + 40.0).abs() < 0.1, "{}", x);
+    }
+
+    #[test]
+    fn local_space_adds_yaw_and_roll() {
+        let mut aim = aim(Gyro {
+            horizontal: Horizontal::Local,
+            ..Gyro::default()
+        });
+        aim.update(&turning(0, [0.0; 3]));
+        let (x, _) = aim.update(&turning(100, [0.0, -30.0, -40.0]));
+        assert!((x - 70.0).abs() < 0.1, "{}", x);
+    }
+
+    #[test]
+    fn player_space_turns_around_up() {
+        let mut aim = aim(Gyro {
+            horizontal: Horizontal::Player,
+            ..Gyro::default()
+        });
+        // tilted 45 degrees towards the player, long enough to tell which way is up
+        let (sin, cos) = 45f32.to_radians().sin_cos();
+        let tilted = |ms, rotation| {
+            let mut state = turning(ms, rotation);
+            state.motion.accel = [0.0, cos, sin].map(|a| (a * ACCEL_RES_PER_G as f32) as i32);
+            state
+        };
+        for ms in (0..10000).step_by(4) {
+            aim.update(&tilted(ms, [0.0; 3]));
+        }
+        // a tenth of a second turning right around up, which is around both yaw & roll
+        let rotation = [0.0, -90.0 * cos, -90.0 * sin];
+        let (x, _) = aim.update(&tilted(10096, rotation));
+        assert!((x - 90.0).abs() < 1.0, "{}", x);
+    }
+
+    #[test]
+    fn ratchet_and_touch_turn_aiming_off() {
+        let mut aim = aim(Gyro {
+            ratchet: Some(Button::L1),
+            on_touch: true,
+            ..Gyro::default()
+        });
+        let mut state = turning(0, [0.0, -90.0, 0.0]);
+        state.touches[0] = Some(crate::hid::Touch::default());
+        aim.update(&state);
+        state.sensor_time = sensor_time(100);
+        assert_ne!(aim.update(&state), (0.0, 0.0));
+
+        state.l1 = true;
+        state.sensor_time = sensor_time(200);
+        assert_eq!(aim.update(&state), (0.0, 0.0));
+
+        state.l1 = false;
+        state.touches[0] = None;
+        state.sensor_time = sensor_time(300);
+        assert_eq!(aim.update(&state), (0.0, 0.0));
+    }
+
+    #[test]
+    fn stick_mode_deflects_by_turning_speed() {
+        let mut aim = Aim::new(&Gyro {
+            mode: Mode::Stick,
+            ..Gyro::default()
+        });
+        let (x, y) = aim.update(&turning(0, [-90.0, 400.0, 0.0]));
+        assert_eq!(x, -1.0);
+        assert!((y - 0.5).abs() < 0.01, "{}", y);
+    }
+
+    #[test]
+    fn curve_shapes_the_rotation_speed() {
+        let mut aim = Aim::new(&Gyro {
+            mode: Mode::Stick,
+            curve: Curve::InQuad,
+            ..Gyro::default()
+        });
+        // half the stick speed deflects it a quarter
+        let (x, _) = aim.update(&turning(0, [0.0, -90.0, 0.0]));
+        assert!((x - 0.25).abs() < 0.01, "{}", x);
+        let (x, _) = aim.update(&turning(4, [0.0, -360.0, 0.0]));
+        assert_eq!(x, 1.0);
+    }
+
+    #[test]
+    fn slow_rotation_is_smoothed_and_tightened() {
+        let mut smoothed = aim(Gyro {
+            smoothing_threshold: 10.0,
+            ..Gyro::default()
+        });
+        smoothed.update(&turning(0, [0.0; 3]));
+        for ms in [4, 8, 12] {
+            smoothed.update(&turning(ms, [0.0; 3]));
+        }
+        // a slow jolt is spread out over the smoothing time
+        let (jolt, _) = smoothed.update(&turning(16, [0.0, -4.0, 0.0]));
+        assert!(jolt > 0.0 && jolt < 4.0 * 0.004 * 10.0 / 2.0, "{}", jolt);
+        // fast turns aren't smoothed
+        let (fast, _) = smoothed.update(&turning(20, [0.0, -100.0, 0.0]));
+        assert!((fast - 4.0).abs() < 0.01, "{}", fast);
+
+        let mut tightened = aim(Gyro {
+            tightening_threshold: 4.0,
+            ..Gyro::default()
+        });
+        tightened.update(&turning(0, [0.0; 3]));
+        // half the threshold is slowed down by half
+        let (x, _) = tightened.update(&turning(250, [0.0, -2.0, 0.0]));
+        assert!((x - 2.5).abs() < 0.01, "{}", x);
+    }
+}
@@ -1,6 +1,74 @@
 use std::fmt::Display;
 
-#[derive(Debug, Default)]
+use serde::{Deserialize, Serialize};
+
+use crate::motion::Motion;
+
+/// Max value of analog sticks & triggers
+pub const ANALOG_MAX: u8 = 255;
+
+/// Width of the touchpad, in the unit of [`Touch`] positions
+pub const TOUCHPAD_WIDTH: u16 = 1920;
+/// Height of the touchpad, in the unit of [`Touch`] positions
+pub const TOUCHPAD_HEIGHT: u16 = 943;
+
+/// Digital inputs of the controller, named as the fields of [`DS4State`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Left,
+    Up,
+    Right,
+    Down,
+    Square,
+    Triangle,
+    Circle,
+    Cross,
+    L1,
+    L2,
+    L3,
+    R1,
+    R2,
+    R3,
+    Select,
+    Start,
+    Touchpad,
+    Ps,
+}
+
+impl Button {
+    pub const ALL: [Button; 18] = [
+        Button::Left,
+        Button::Up,
+        Button::Right,
+        Button::Down,
+        Button::Square,
+        Button::Triangle,
+        Button::Circle,
+        Button::Cross,
+        Button::L1,
+        Button::L2,
+        Button::L3,
+        Button::R1,
+        Button::R2,
+        Button::R3,
+        Button::Select,
+        Button::Start,
+        Button::Touchpad,
+        Button::Ps,
+    ];
+}
+
+/// Finger on the touchpad, positioned from its top left corner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Touch {
+    /// Counts up with every finger put down, a finger keeps its id until lifted
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct DS4State {
     pub left: bool,
     pub up: bool,
@@ -27,6 +95,17 @@ pub struct DS4State {
     pub rsx: u8,
     pub rsy: u8,
     pub timer: u8,
+    /// Up to two fingers on the touchpad
+    pub touches: [Option<Touch>; 2],
+    /// Counts up in units of 16/3 µs, the time the motion sensors were read
+    pub sensor_time: u16,
+    /// Raw rotation rate around the pitch, yaw & roll axes
+    pub gyro: [i16; 3],
+    /// Raw acceleration along the x, y & z axes
+    pub accel: [i16; 3],
+    /// Readings of the motion sensors scaled with the controller's calibration, zero until
+    /// [`crate::motion::Calibration::apply`] fills them in
+    pub motion: Motion,
 }
 
 impl DS4State {
@@ -35,6 +114,84 @@ impl DS4State {
             ..Default::default()
         }
     }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        *self.button(button)
+    }
+
+    pub fn set_pressed(&mut self, button: Button, pressed: bool) {
+        *self.button_mut(button) = pressed;
+    }
+
+    fn button(&self, button: Button) -> &bool {
+        match button {
+            Button::Left => &self.left,
+            Button::Up => &self.up,
+            Button::Right => &self.right,
+            Button::Down => &self.down,
+            Button::Square => &self.square,
+            Button::Triangle => &self.triangle,
+            Button::Circle => &self.circle,
+            Button::Cross => &self.cross,
+            Button::L1 => &self.l1,
+            Button::L2 => &self.l2,
+            Button::L3 => &self.l3,
+            Button::R1 => &self.r1,
+            Button::R2 => &self.r2,
+            Button::R3 => &self.r3,
+            Button::Select => &self.select,
+            Button::Start => &self.start,
+            Button::Touchpad => &self.touchpad,
+            Button::Ps => &self.ps,
+        }
+    }
+
+    fn button_mut(&mut self, button: Button) -> &mut bool {
+        match button {
+            Button::Left => &mut self.left,
+            Button::Up => &mut self.up,
+            Button::Right => &mut self.right,
+            Button::Down => &mut self.down,
+            Button::Square => &mut self.square,
+            Button::Triangle => &mut self.triangle,
+            Button::Circle => &mut self.circle,
+            Button::Cross => &mut self.cross,
+            Button::L1 => &mut self.l1,
+            Button::L2 => &mut self.l2,
+            Button::L3 => &mut self.l3,
+            Button::R1 => &mut self.r1,
+            Button::R2 => &mut self.r2,
+            Button::R3 => &mut self.r3,
+            Button::Select => &mut self.select,
+            Button::Start => &mut self.start,
+            Button::Touchpad => &mut self.touchpad,
+            Button::Ps => &mut self.ps,
+        }
+    }
+}
+
+/// Rumble & lightbar state sent to the controller
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Feedback {
+    /// Strength of the small, high frequency motor on the right
+    pub rumble_weak: u8,
+    /// Strength of the big, low frequency motor on the left
+    pub rumble_strong: u8,
+    pub lightbar: [u8; 3],
+}
+
+impl Feedback {
+    /// USB output report (id 0x05) applying the feedback
+    pub fn report(&self) -> [u8; 32] {
+        let mut buf = [0; 32];
+        buf[0] = 0x05;
+        // valid flags: rumble motors & lightbar color
+        buf[1] = 0x01 | 0x02;
+        buf[4] = self.rumble_weak;
+        buf[5] = self.rumble_strong;
+        buf[6..9].copy_from_slice(&self.lightbar);
+        buf
+    }
 }
 
 impl Display for DS4State {
@@ -126,6 +283,21 @@ impl From<&[u8; 64]> for DS4State {
         let touchpad = buf[7] & 0x02 != 0;
         let timer = buf[7] >> 2;
 
+        // analog triggers
+        let l2_analog = buf[8];
+        let r2_analog = buf[9];
+
+        // touchpad fingers, of the first touch packet
+        let touches = [touch(&buf[35..39]), touch(&buf[39..43])];
+
+        // motion sensors, little endian
+        let sensor_time = u16::from_le_bytes([buf[10], buf[11]]);
+        let axes = |data: &[u8]| -> [i16; 3] {
+            [0, 2, 4].map(|i| i16::from_le_bytes([data[i], data[i + 1]]))
+        };
+        let gyro = axes(&buf[13..19]);
+        let accel = axes(&buf[19..25]);
+
         Self {
             left,
             up,
@@ -142,7 +314,9 @@ impl From<&[u8; 64]> for DS4State {
             l1,
             r1,
             l2,
+            l2_analog,
             r2,
+            r2_analog,
             l3,
             r3,
             select,
@@ -150,16 +324,57 @@ impl From<&[u8; 64]> for DS4State {
             ps,
             touchpad,
             timer,
-            ..Default::default()
+            touches,
+            sensor_time,
+            gyro,
+            accel,
+            motion: Motion::default(),
         }
     }
 }
 
+/// Finger of touch packet `data`, if it's on the pad
+fn touch(data: &[u8]) -> Option<Touch> {
+    // the high bit is set while the finger is lifted
+    if data[0] & 0x80 != 0 {
+        return None;
+    }
+    Some(Touch {
+        id: data[0] & 0x7f,
+        x: data[1] as u16 | (data[2] as u16 & 0x0f) << 8,
+        y: (data[2] as u16) >> 4 | (data[3] as u16) << 4,
+    })
+}
+
 #[allow(non_snake_case)]
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn pressed_reads_matching_field() {
+        let mut state = DS4State::initial_state();
+        state.r3 = true;
+        assert!(state.pressed(Button::R3));
+        assert!(!state.pressed(Button::L3));
+        state.set_pressed(Button::R3, false);
+        assert!(!state.r3);
+    }
+
+    #[test]
+    fn feedback_report_layout() {
+        let feedback = Feedback {
+            rumble_weak: 0x10,
+            rumble_strong: 0x20,
+            lightbar: [0xFF, 0x80, 0x00],
+        };
+        let report = feedback.report();
+        assert_eq!(
+            report[..11],
+            [0x05, 0x03, 0x00, 0x00, 0x10, 0x20, 0xFF, 0x80, 0x00, 0x00, 0x00]
+        );
+    }
+
     macro_rules! assert_attrs {
         ($object:ident: $($attr:ident $op:tt $value:expr,)*) => {
             $(
@@ -315,5 +530,20 @@ mod tests {
         ps_button_and_touchpad_NOT_pressed:
             "01 7F 7F 7F FF F0 80 00 00 00 0D AF FF E9 FF EE FF F2 FF 28 03 23 20 FF FF 00 00 00 00 00 1B 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00";
             ps == false; touchpad == false;,
+        // analog trigger values found on byte indexes 8 & 9, in order: l2, r2
+        analog_triggers_pressed:
+            "01 7F 7F 7F FF 08 0C 00 40 FF 0D AF FF E9 FF EE FF F2 FF 28 03 23 20 FF FF 00 00 00 00 00 1B 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00";
+            l2 == true; r2 == true; l2_analog == 0x40; r2_analog == 0xFF;,
+        // fingers found on byte indexes 35-38 & 39-42: touching bit & id, then 12 bit x & y
+        one_finger_touching:
+            "01 7F 7F 7F FF 08 00 00 00 00 0D AF FF E9 FF EE FF F2 FF 28 03 23 20 FF FF 00 00 00 00 00 1B 00 00 01 00 05 23 41 2A 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00";
+            touches == [Some(Touch { id: 5, x: 0x123, y: 0x2A4 }), None];,
+        two_fingers_touching:
+            "01 7F 7F 7F FF 08 00 00 00 00 0D AF FF E9 FF EE FF F2 FF 28 03 23 20 FF FF 00 00 00 00 00 1B 00 00 01 00 05 23 41 2A 06 7F F7 3A 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00";
+            touches == [Some(Touch { id: 5, x: 0x123, y: 0x2A4 }), Some(Touch { id: 6, x: 0x77F, y: 0x3AF })];,
+        // motion sensors found on byte indexes 10-11 (timestamp), 13-18 (gyro) & 19-24 (accelerometer)
+        motion_sensors:
+            "01 7F 7F 7F FF 08 00 00 00 00 0D AF FF E9 FF EE FF F2 FF 28 03 23 20 FF FF 00 00 00 00 00 1B 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00 00 00 80 00 00 00 00 80 00";
+            sensor_time == 0xAF0D; gyro == [-23, -18, -14]; accel == [808, 8227, -1];,
     }
 }
@@ -0,0 +1,419 @@
+use anyhow::{bail, Context, Result};
+use evdev_rs::enums::EV_KEY;
+use roxmltree::{Document, Node};
+
+use crate::{
+    curve::Curve,
+    profile::{Axis, Profile},
+};
+
+/// Profile converted from another driver's format, along with the settings it had that couldn't
+/// be carried over
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub profile: Profile,
+    pub unsupported: Vec<String>,
+}
+
+/// Convert a DS4Windows profile, as saved in its `Profiles` directory.
+///
+/// Settings which are turned off (`0`, `False` or empty) are left out of
+/// [`Import::unsupported`], as they have nothing to convert.
+pub fn ds4windows(xml: &str) -> Result<Import> {
+    let document = Document::parse(xml).context("Not a valid XML file")?;
+    let root = document.root_element();
+    if !root.has_tag_name("DS4Windows") {
+        bail!(
+            "Not a DS4Windows profile, its root element is <{}>",
+            root.tag_name().name()
+        );
+    }
+
+    let mut import = Import {
+        profile: Profile::default(),
+        unsupported: vec![],
+    };
+    // DS4Windows doesn't have this driver's custom default curve
+    for axis in [
+        &mut import.profile.left_stick.x,
+        &mut import.profile.left_stick.y,
+    ] {
+        axis.curve = Curve::Linear;
+    }
+    let mut color = [None; 3];
+
+    for element in root.children().filter(Node::is_element) {
+        let name = element.tag_name().name();
+        let text = element.text().unwrap_or("").trim();
+        let (left, right) = (
+            &mut import.profile.left_stick,
+            &mut import.profile.right_stick,
+        );
+        let handled = match name {
+            "LSDeadZone" => set_axes([&mut left.x, &mut left.y], text, |axis, v: u8| {
+                axis.deadzone = v.min(127)
+            }),
+            "RSDeadZone" => set_axes([&mut right.x, &mut right.y], text, |axis, v: u8| {
+                axis.deadzone = v.min(127)
+            }),
+            "LSAntiDeadZone" => set_axes([&mut left.x, &mut left.y], text, |axis, v: f32| {
+                axis.anti_deadzone = (v / 100.0).clamp(0.0, 1.0)
+            }),
+            "RSAntiDeadZone" => set_axes([&mut right.x, &mut right.y], text, |axis, v: f32| {
+                axis.anti_deadzone = (v / 100.0).clamp(0.0, 1.0)
+            }),
+            "LSSens" => set_axes([&mut left.x, &mut left.y], text, |axis, v: f32| {
+                axis.sensitivity = v
+            }),
+            "RSSens" => set_axes([&mut right.x, &mut right.y], text, |axis, v: f32| {
+                axis.sensitivity = v
+            }),
+            "LSOutputCurveMode" | "RSOutputCurveMode" => {
+                let custom = root
+                    .children()
+                    .find(|e| e.has_tag_name(&*name.replace("Mode", "Custom")))
+                    .and_then(|e| e.text());
+                match output_curve(text, custom) {
+                    Some(curve) => {
+                        let stick = if name.starts_with('L') { left } else { right };
+                        stick.x.curve = curve.clone();
+                        stick.y.curve = curve;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            // read along with the curve mode
+            "LSOutputCurveCustom" | "RSOutputCurveCustom" => true,
+            "Color" => match parse_color(text) {
+                Some(rgb) => {
+                    color = rgb.map(Some);
+                    true
+                }
+                None => false,
+            },
+            "Red" | "Green" | "Blue" => {
+                let i = ["Red", "Green", "Blue"].iter().position(|c| *c == name);
+                match (i, text.parse()) {
+                    (Some(i), Ok(value)) => {
+                        color[i] = Some(value);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            "Control" => {
+                import_controls(element, &mut import);
+                true
+            }
+            _ => false,
+        };
+        if !handled && !is_off(text) {
+            import
+                .unsupported
+                .push(format!("{}: {}", name, text.replace('\n', " ")));
+        }
+    }
+
+    if let [Some(r), Some(g), Some(b)] = color {
+        import.profile.lightbar = [r, g, b];
+    }
+    Ok(import)
+}
+
+fn set_axes<T: std::str::FromStr + Copy>(
+    axes: [&mut Axis; 2],
+    text: &str,
+    set: impl Fn(&mut Axis, T),
+) -> bool {
+    match text.parse() {
+        Ok(value) => {
+            for axis in axes {
+                set(axis, value);
+            }
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn output_curve(mode: &str, custom: Option<&str>) -> Option<Curve> {
+    Some(match mode {
+        "linear" => Curve::Linear,
+        "quadratic" => Curve::InQuad,
+        "cubic" => Curve::Cubic,
+        "easeout-quad" => Curve::OutQuad,
+        // DS4Windows' piecewise curve for fine aiming
+        "enhanced-precision" => Curve::Points(vec![[0.4, 0.22], [0.75, 0.57], [1.0, 1.0]]),
+        "easeout-cubic" => Curve::Points(
+            (1..=8)
+                .map(|i| {
+                    let x = i as f32 / 8.0;
+                    let y = 1.0 - (1.0 - x).powi(3);
+                    [x, (y * 100.0).round() / 100.0]
+                })
+                .collect(),
+        ),
+        "custom" => {
+            let values: Vec<f32> = custom?
+                .split(',')
+                .map(|v| v.trim().parse())
+                .collect::<Result<_, _>>()
+                .ok()?;
+            Curve::Bezier(values.try_into().ok()?)
+        }
+        _ => return None,
+    })
+}
+
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let values: Vec<u8> = text
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    values.try_into().ok()
+}
+
+/// Whether a setting is turned off, and not worth reporting when it can't be converted
+fn is_off(text: &str) -> bool {
+    matches!(text, "" | "0" | "False" | "false")
+}
+
+/// Import the `<Control>` section, which remaps buttons to virtual Xbox 360 buttons (`<Button>`)
+/// or keyboard keys by Windows virtual key code (`<Key>`)
+fn import_controls(control: Node, import: &mut Import) {
+    for section in control.children().filter(Node::is_element) {
+        let kind = section.tag_name().name();
+        for binding in section.children().filter(Node::is_element) {
+            let input = binding.tag_name().name();
+            let value = binding.text().unwrap_or("").trim();
+            let code = match kind {
+                "Button" => xbox_button(value),
+                "Key" => value.parse().ok().and_then(virtual_key),
+                _ => None,
+            };
+            let slot = button_slot(&mut import.profile, input);
+            match (code, slot) {
+                (Some(code), Some(slot)) => *slot = code,
+                _ => import
+                    .unsupported
+                    .push(format!("Control.{}.{}: {}", kind, input, value)),
+            }
+        }
+    }
+}
+
+/// Binding of the DS4Windows control named `input`
+fn button_slot<'a>(profile: &'a mut Profile, input: &str) -> Option<&'a mut EV_KEY> {
+    let buttons = &mut profile.buttons;
+    Some(match input {
+        "Cross" => &mut buttons.cross,
+        "Circle" => &mut buttons.circle,
+        "Square" => &mut buttons.square,
+        "Triangle" => &mut buttons.triangle,
+        "L1" => &mut buttons.l1,
+        "L2" => &mut buttons.l2,
+        "L3" => &mut buttons.l3,
+        "R1" => &mut buttons.r1,
+        "R2" => &mut buttons.r2,
+        "R3" => &mut buttons.r3,
+        "Share" => &mut buttons.select,
+        "Options" => &mut buttons.start,
+        "PS" => &mut buttons.ps,
+        "TouchButton" => &mut buttons.touchpad,
+        _ => return None,
+    })
+}
+
+/// Code of a DS4Windows virtual Xbox 360 button
+fn xbox_button(name: &str) -> Option<EV_KEY> {
+    Some(match name {
+        "A Button" => EV_KEY::BTN_SOUTH,
+        "B Button" => EV_KEY::BTN_EAST,
+        "X Button" => EV_KEY::BTN_WEST,
+        "Y Button" => EV_KEY::BTN_NORTH,
+        "Left Bumper" => EV_KEY::BTN_TL,
+        "Right Bumper" => EV_KEY::BTN_TR,
+        "Left Trigger" => EV_KEY::BTN_TL2,
+        "Right Trigger" => EV_KEY::BTN_TR2,
+        "Back" => EV_KEY::BTN_SELECT,
+        "Start" => EV_KEY::BTN_START,
+        "Guide" => EV_KEY::BTN_MODE,
+        "Left Stick" => EV_KEY::BTN_THUMBL,
+        "Right Stick" => EV_KEY::BTN_THUMBR,
+        "Up Button" => EV_KEY::BTN_DPAD_UP,
+        "Down Button" => EV_KEY::BTN_DPAD_DOWN,
+        "Left Button" => EV_KEY::BTN_DPAD_LEFT,
+        "Right Button" => EV_KEY::BTN_DPAD_RIGHT,
+        _ => return None,
+    })
+}
+
+/// Code of a Windows virtual key, for the common keys of a US keyboard
+fn virtual_key(vk: u32) -> Option<EV_KEY> {
+    const LETTERS: [EV_KEY; 26] = [
+        EV_KEY::KEY_A,
+        EV_KEY::KEY_B,
+        EV_KEY::KEY_C,
+        EV_KEY::KEY_D,
+        EV_KEY::KEY_E,
+        EV_KEY::KEY_F,
+        EV_KEY::KEY_G,
+        EV_KEY::KEY_H,
+        EV_KEY::KEY_I,
+        EV_KEY::KEY_J,
+        EV_KEY::KEY_K,
+        EV_KEY::KEY_L,
+        EV_KEY::KEY_M,
+        EV_KEY::KEY_N,
+        EV_KEY::KEY_O,
+        EV_KEY::KEY_P,
+        EV_KEY::KEY_Q,
+        EV_KEY::KEY_R,
+        EV_KEY::KEY_S,
+        EV_KEY::KEY_T,
+        EV_KEY::KEY_U,
+        EV_KEY::KEY_V,
+        EV_KEY::KEY_W,
+        EV_KEY::KEY_X,
+        EV_KEY::KEY_Y,
+        EV_KEY::KEY_Z,
+    ];
+    const DIGITS: [EV_KEY; 10] = [
+        EV_KEY::KEY_0,
+        EV_KEY::KEY_1,
+        EV_KEY::KEY_2,
+        EV_KEY::KEY_3,
+        EV_KEY::KEY_4,
+        EV_KEY::KEY_5,
+        EV_KEY::KEY_6,
+        EV_KEY::KEY_7,
+        EV_KEY::KEY_8,
+        EV_KEY::KEY_9,
+    ];
+    const FUNCTION: [EV_KEY; 12] = [
+        EV_KEY::KEY_F1,
+        EV_KEY::KEY_F2,
+        EV_KEY::KEY_F3,
+        EV_KEY::KEY_F4,
+        EV_KEY::KEY_F5,
+        EV_KEY::KEY_F6,
+        EV_KEY::KEY_F7,
+        EV_KEY::KEY_F8,
+        EV_KEY::KEY_F9,
+        EV_KEY::KEY_F10,
+        EV_KEY::KEY_F11,
+        EV_KEY::KEY_F12,
+    ];
+    Some(match vk {
+        0x08 => EV_KEY::KEY_BACKSPACE,
+        0x09 => EV_KEY::KEY_TAB,
+        0x0D => EV_KEY::KEY_ENTER,
+        0x10 | 0xA0 => EV_KEY::KEY_LEFTSHIFT,
+        0xA1 => EV_KEY::KEY_RIGHTSHIFT,
+        0x11 | 0xA2 => EV_KEY::KEY_LEFTCTRL,
+        0xA3 => EV_KEY::KEY_RIGHTCTRL,
+        0x12 | 0xA4 => EV_KEY::KEY_LEFTALT,
+        0xA5 => EV_KEY::KEY_RIGHTALT,
+        0x1B => EV_KEY::KEY_ESC,
+        0x20 => EV_KEY::KEY_SPACE,
+        0x21 => EV_KEY::KEY_PAGEUP,
+        0x22 => EV_KEY::KEY_PAGEDOWN,
+        0x23 => EV_KEY::KEY_END,
+        0x24 => EV_KEY::KEY_HOME,
+        0x25 => EV_KEY::KEY_LEFT,
+        0x26 => EV_KEY::KEY_UP,
+        0x27 => EV_KEY::KEY_RIGHT,
+        0x28 => EV_KEY::KEY_DOWN,
+        0x2D => EV_KEY::KEY_INSERT,
+        0x2E => EV_KEY::KEY_DELETE,
+        0x30..=0x39 => DIGITS[(vk - 0x30) as usize],
+        0x41..=0x5A => LETTERS[(vk - 0x41) as usize],
+        0x70..=0x7B => FUNCTION[(vk - 0x70) as usize],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- DS4Windows Configuration Data. 01/02/2023 10:20:30 -->
+<DS4Windows app_version="3.2.8">
+  <Color>255,0,128</Color>
+  <LSDeadZone>10</LSDeadZone>
+  <RSDeadZone>20</RSDeadZone>
+  <LSAntiDeadZone>25</LSAntiDeadZone>
+  <RSAntiDeadZone>0</RSAntiDeadZone>
+  <LSSens>1.5</LSSens>
+  <RSSens>1</RSSens>
+  <LSOutputCurveMode>custom</LSOutputCurveMode>
+  <LSOutputCurveCustom>0.50,0.00,1.00,0.50</LSOutputCurveCustom>
+  <RSOutputCurveMode>enhanced-precision</RSOutputCurveMode>
+  <GyroSensitivity>100</GyroSensitivity>
+  <FlushHIDQueue>False</FlushHIDQueue>
+  <Control>
+    <Button>
+      <Cross>B Button</Cross>
+      <Circle>A Button</Circle>
+      <DpadUp>Y Button</DpadUp>
+    </Button>
+    <Key>
+      <Share>87</Share>
+    </Key>
+    <Macro>
+      <Options>65/66</Options>
+    </Macro>
+  </Control>
+</DS4Windows>"#;
+
+    #[test]
+    fn sticks_are_imported() {
+        let import = ds4windows(PROFILE).unwrap();
+        let left = &import.profile.left_stick;
+        assert_eq!(left.x.deadzone, 10);
+        assert_eq!(left.y.anti_deadzone, 0.25);
+        assert_eq!(left.x.sensitivity, 1.5);
+        assert_eq!(left.y.curve, Curve::Bezier([0.5, 0.0, 1.0, 0.5]));
+        let right = &import.profile.right_stick;
+        assert_eq!(right.y.deadzone, 20);
+        assert_eq!(right.x.anti_deadzone, 0.0);
+        assert!(matches!(right.x.curve, Curve::Points(_)));
+        assert_eq!(import.profile.lightbar, [255, 0, 128]);
+    }
+
+    #[test]
+    fn buttons_are_remapped() {
+        let buttons = ds4windows(PROFILE).unwrap().profile.buttons;
+        assert_eq!(buttons.cross, EV_KEY::BTN_EAST);
+        assert_eq!(buttons.circle, EV_KEY::BTN_SOUTH);
+        assert_eq!(buttons.select, EV_KEY::KEY_W);
+        assert_eq!(buttons.square, Profile::default().buttons.square);
+    }
+
+    #[test]
+    fn unsupported_settings_are_listed() {
+        assert_eq!(
+            ds4windows(PROFILE).unwrap().unsupported,
+            [
+                "GyroSensitivity: 100",
+                "Control.Button.DpadUp: Y Button",
+                "Control.Macro.Options: 65/66",
+            ]
+        );
+    }
+
+    #[test]
+    fn old_color_format_is_read() {
+        let xml = "<DS4Windows><Red>1</Red><Green>2</Green><Blue>3</Blue></DS4Windows>";
+        assert_eq!(ds4windows(xml).unwrap().profile.lightbar, [1, 2, 3]);
+    }
+
+    #[test]
+    fn other_xml_is_rejected() {
+        assert!(ds4windows("<Profile></Profile>").is_err());
+        assert!(ds4windows("DS4Windows").is_err());
+    }
+}
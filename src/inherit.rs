@@ -0,0 +1,226 @@
+use toml::{Table, Value};
+
+use crate::{profile::Profile, validate::Problem};
+
+/// Keys whose values replace the inherited one as a whole, even when they're tables. A curve is a
+/// single setting written as a table, merging two of them would make neither.
+const ATOMIC_KEYS: [&str; 1] = ["curve"];
+/// Keys of a profile which profiles extending it don't inherit. Applications matching the base
+/// shouldn't switch to every profile extending it.
+const NOT_INHERITED: [&str; 1] = ["match"];
+
+/// Resolve `extends` of every profile in the `profiles` table of `config`, so each profile holds
+/// all of its settings. The `extends` keys themselves are kept.
+pub fn resolve(config: &mut Table) -> Result<(), Vec<Problem>> {
+    let Some(Value::Table(profiles)) = config.get("profiles") else {
+        return Ok(());
+    };
+    let mut resolved = Table::new();
+    let mut problems = vec![];
+    for name in profiles.keys() {
+        match resolve_profile(profiles, name, &mut vec![]) {
+            Ok(profile) => {
+                resolved.insert(name.clone(), Value::Table(profile));
+            }
+            Err(problem) => {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    config.insert("profiles".to_string(), Value::Table(resolved));
+    Ok(())
+}
+
+fn resolve_profile(
+    profiles: &Table,
+    name: &str,
+    chain: &mut Vec<String>,
+) -> Result<Table, Problem> {
+    let profile = match profiles.get(name) {
+        Some(Value::Table(profile)) => profile,
+        // not a table, which deserializing reports
+        _ => return Ok(Table::new()),
+    };
+    let Some(base) = profile.get("extends").and_then(Value::as_str) else {
+        return Ok(profile.clone());
+    };
+    chain.push(name.to_string());
+    let key = |name: &str| {
+        vec![
+            "profiles".to_string(),
+            name.to_string(),
+            "extends".to_string(),
+        ]
+    };
+    if let Some(start) = chain.iter().position(|n| n == base) {
+        // every profile of the cycle leads to it, so it's reported once, starting at the first name
+        let mut cycle = chain[start..].to_vec();
+        let first = (0..cycle.len()).min_by_key(|i| &cycle[*i]).unwrap_or(0);
+        cycle.rotate_left(first);
+        cycle.push(cycle[0].clone());
+        return Err(Problem {
+            key: key(&cycle[0]),
+            message: format!("`{}` is a cycle", cycle.join("` -> `")),
+        });
+    }
+    if !profiles.contains_key(base) {
+        return Err(Problem {
+            key: key(name),
+            message: format!("there is no profile named `{}`", base),
+        });
+    }
+    let mut resolved = inherited(complete(resolve_profile(profiles, base, chain)?));
+    merge(&mut resolved, profile);
+    Ok(resolved)
+}
+
+/// Write out every setting of `profile`, so tables of a profile extending it merge with the
+/// settings of the base rather than the defaults
+fn complete(profile: Table) -> Table {
+    Value::Table(profile.clone())
+        .try_into::<Profile>()
+        .ok()
+        .and_then(|profile| Table::try_from(profile).ok())
+        .unwrap_or(profile)
+}
+
+/// Settings of profile `base` which profiles extending it inherit
+pub fn inherited(mut base: Table) -> Table {
+    for key in NOT_INHERITED {
+        base.remove(key);
+    }
+    base
+}
+
+/// Merge `overlay` into `base`: tables merge, any other value (lists included) replaces the one in
+/// `base`.
+///
+/// # Examples
+///
+/// ```
+/// use ds4linux::inherit::merge;
+///
+/// let mut base: toml::Table = toml::from_str("a = 1\nlist = [1, 2]\n[t]\nb = 2\nc = 3").unwrap();
+/// let overlay: toml::Table = toml::from_str("list = [3]\n[t]\nc = 4").unwrap();
+/// merge(&mut base, &overlay);
+/// assert_eq!(base, toml::from_str("a = 1\nlist = [3]\n[t]\nb = 2\nc = 4").unwrap());
+/// ```
+pub fn merge(base: &mut Table, overlay: &Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay))
+                if !ATOMIC_KEYS.contains(&key.as_str()) =>
+            {
+                merge(base, overlay)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The inverse of [`merge`]: settings of `table` which differ from those in `base`
+pub fn diff(base: &Table, table: &Table) -> Table {
+    let mut changed = Table::new();
+    for (key, value) in table {
+        match (base.get(key), value) {
+            (Some(base), value) if base == value => {}
+            (Some(Value::Table(base)), Value::Table(table))
+                if !ATOMIC_KEYS.contains(&key.as_str()) =>
+            {
+                let table = diff(base, table);
+                if !table.is_empty() {
+                    changed.insert(key.clone(), Value::Table(table));
+                }
+            }
+            _ => {
+                changed.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(source: &str) -> Result<Table, Vec<Problem>> {
+        let mut config: Table = toml::from_str(source).unwrap();
+        resolve(&mut config).map(|_| config)
+    }
+
+    #[test]
+    fn profiles_inherit_through_chains() {
+        let config = resolved(
+            "[profiles.base]\nlightbar = [1, 2, 3]\n[profiles.base.buttons]\ncross = \"KEY_A\"\n\
+             [profiles.shooter]\nextends = \"base\"\n[profiles.shooter.buttons]\nsquare = \"KEY_B\"\n\
+             [profiles.game]\nextends = \"shooter\"\nlightbar = [4, 5, 6]\n",
+        )
+        .unwrap();
+        let game = &config["profiles"]["game"];
+        assert_eq!(
+            game["lightbar"],
+            toml::from_str::<Table>("v = [4, 5, 6]").unwrap()["v"]
+        );
+        assert_eq!(game["buttons"]["cross"].as_str(), Some("KEY_A"));
+        assert_eq!(game["buttons"]["square"].as_str(), Some("KEY_B"));
+        assert_eq!(game["extends"].as_str(), Some("shooter"));
+    }
+
+    #[test]
+    fn curves_replace_instead_of_merging() {
+        let config = resolved(
+            "[profiles.base.left_stick.x]\ncurve = { points = [[1.0, 1.0]] }\n\
+             [profiles.game]\nextends = \"base\"\n[profiles.game.left_stick.x]\ncurve = { bezier = [0.0, 0.0, 1.0, 1.0] }\n",
+        )
+        .unwrap();
+        let curve = config["profiles"]["game"]["left_stick"]["x"]["curve"]
+            .as_table()
+            .unwrap();
+        assert_eq!(curve.keys().collect::<Vec<_>>(), ["bezier"]);
+    }
+
+    #[test]
+    fn match_rules_arent_inherited() {
+        let config = resolved(
+            "[profiles.base.match]\nprocess = [\"game\"]\n\
+             [profiles.game]\nextends = \"base\"\n\
+             [profiles.other]\nextends = \"base\"\n[profiles.other.match]\nprocess = [\"other\"]\n",
+        )
+        .unwrap();
+        let profiles = config["profiles"].as_table().unwrap();
+        assert!(profiles["base"].get("match").is_some());
+        assert_eq!(profiles["game"].get("match"), None);
+        assert_eq!(
+            profiles["other"]["match"]["process"].as_array().unwrap()[0].as_str(),
+            Some("other")
+        );
+    }
+
+    #[test]
+    fn missing_base_and_cycles_are_problems() {
+        let problems = resolved("[profiles.game]\nextends = \"nope\"\n").unwrap_err();
+        assert_eq!(problems[0].key, ["profiles", "game", "extends"]);
+
+        let problems =
+            resolved("[profiles.a]\nextends = \"b\"\n[profiles.b]\nextends = \"a\"\n").unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("is a cycle"));
+    }
+
+    #[test]
+    fn diff_undoes_merge() {
+        let base: Table = toml::from_str("a = 1\n[t]\nb = 2\nc = 3").unwrap();
+        let overlay: Table = toml::from_str("a = 1\n[t]\nc = 4").unwrap();
+        let mut merged = base.clone();
+        merge(&mut merged, &overlay);
+        assert_eq!(diff(&base, &merged), toml::from_str("[t]\nc = 4").unwrap());
+    }
+}
@@ -1,2 +1,22 @@
-pub mod hid;
+pub mod bias;
+pub mod config;
 pub mod curve;
+pub mod flick;
+pub mod fusion;
+pub mod gesture;
+pub mod gyro;
+pub mod hid;
+pub mod import;
+pub mod inherit;
+pub mod mapping;
+pub mod motion;
+pub mod movement;
+pub mod multitouch;
+pub mod preset;
+pub mod process;
+pub mod profile;
+pub mod reload;
+pub mod switch;
+pub mod tilt;
+pub mod touchpad;
+pub mod validate;
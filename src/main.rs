@@ -1,22 +1,51 @@
+mod cli;
+mod tui;
+
 use std::{
-    io::{stdout, Write},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    collections::BTreeMap,
+    env, fs,
+    io::{self, stdout, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::Receiver,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
-
-use ds4linux::{curve, hid::DS4State};
+use anyhow::{bail, Context as _, Result};
+
+use crossterm::event::{self, Event};
+use ds4linux::{
+    bias::{Biases, Estimator, Stillness},
+    config::Config,
+    hid::{DS4State, Feedback},
+    import,
+    mapping::{self, Capabilities, EventSink, Mapping, Reporter},
+    motion::{self, Calibration, Sensors},
+    multitouch::{self, Contacts},
+    preset::{Identity, Preset},
+    process,
+    profile::Profile,
+    reload,
+    switch::{self, Switcher},
+    validate,
+};
 use evdev_rs::{
-    enums::{BusType, EventCode, EventType, EV_ABS, EV_KEY, EV_SYN},
+    enums::{BusType, EventCode, EventType, EV_SYN},
     AbsInfo, TimeVal,
 };
 use evdev_rs::{DeviceWrapper, InputEvent, UInputDevice, UninitDevice};
 use rusb::{Context, Device, DeviceHandle, UsbContext};
+use serde::Serialize;
 
 const VID: u16 = 0x054c;
 const PID: u16 = 0x05c4;
 
-const ANALOG_MAX: u8 = 255;
+const TUI_FRAME_TIME: Duration = Duration::from_millis(33);
+// how long the controller rumbles to confirm a profile switch
+const SWITCH_RUMBLE_TIME: Duration = Duration::from_millis(150);
+// degrees per second the gyro bias changes by before it's saved again
+const BIAS_SAVE_CHANGE: f32 = 0.1;
+// how long the calibrate command waits for the controller to lie still
+const CALIBRATE_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 struct Endpoint {
@@ -26,6 +55,98 @@ struct Endpoint {
     address: u8,
 }
 
+const DESKTOP: Identity = Identity {
+    name: "ds4linux keyboard & mouse",
+    bustype: BusType::BUS_USB,
+    vendor: 0,
+    product: 0,
+    version: 0,
+};
+
+const TOUCHPAD: Identity = Identity {
+    name: "Sony Computer Entertainment Wireless Controller Touchpad",
+    bustype: BusType::BUS_USB,
+    vendor: 0x054c,
+    product: 0x05c4,
+    version: 0x8111,
+};
+
+const MOTION: Identity = Identity {
+    name: "Sony Computer Entertainment Wireless Controller Motion Sensors",
+    bustype: BusType::BUS_USB,
+    vendor: 0x054c,
+    product: 0x05c4,
+    version: 0x8111,
+};
+
+/// The uinput devices, writing events stamped with the time of the controller report. Keyboard
+/// keys & mouse events go to the keyboard & mouse device, everything else to the gamepad.
+struct VirtualDevices<'a> {
+    gamepad: &'a UInputDevice,
+    desktop: Option<&'a UInputDevice>,
+    time: TimeVal,
+}
+
+impl VirtualDevices<'_> {
+    fn write_to(&self, device: &UInputDevice, event_code: EventCode, value: i32) -> io::Result<()> {
+        device.write_event(&InputEvent {
+            time: self.time,
+            event_code,
+            value,
+        })
+    }
+}
+
+impl EventSink for VirtualDevices<'_> {
+    fn write(&mut self, event_code: EventCode, value: i32) -> io::Result<()> {
+        let desktop = match event_code {
+            EventCode::EV_KEY(key) => mapping::is_desktop(key),
+            EventCode::EV_REL(_) => true,
+            _ => false,
+        };
+        match self.desktop {
+            Some(device) if desktop => self.write_to(device, event_code, value),
+            _ => self.write_to(self.gamepad, event_code, value),
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let report = EventCode::EV_SYN(EV_SYN::SYN_REPORT);
+        if let Some(desktop) = self.desktop {
+            self.write_to(desktop, report, 0)?;
+        }
+        self.write_to(self.gamepad, report, 0)
+    }
+}
+
+/// Keyboard & mouse device with the codes of the active profile, created again when they change
+#[derive(Default)]
+struct Desktop {
+    /// Codes of the mapping the device was created for
+    capabilities: Capabilities,
+    device: Option<UInputDevice>,
+}
+
+impl Desktop {
+    fn update(&mut self, mapping: &Mapping) -> Result<()> {
+        if *mapping.capabilities() == self.capabilities {
+            return Ok(());
+        }
+        let (_, desktop) = mapping.capabilities().clone().split_desktop();
+        let (_, current) = self.capabilities.clone().split_desktop();
+        self.capabilities = mapping.capabilities().clone();
+        if desktop == current {
+            return Ok(());
+        }
+        // removing the device releases its keys
+        self.device = None;
+        if desktop != Capabilities::default() {
+            self.device = Some(create_device(&DESKTOP, &desktop)?);
+        }
+        Ok(())
+    }
+}
+
 fn event_time_now() -> TimeVal {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -35,6 +156,31 @@ fn event_time_now() -> TimeVal {
 }
 
 fn main() -> Result<()> {
+    let (config_arg, tui) = match cli::Command::parse(env::args().skip(1))? {
+        cli::Command::Run { config, tui } => (config, tui),
+        cli::Command::CheckConfig { file } => return check_config(file),
+        cli::Command::ImportConfig { file, name } => return import_config(&file, name),
+        cli::Command::DumpConfig { file, resolved } => return dump_config(file, resolved),
+        cli::Command::Calibrate => return calibrate(),
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+    };
+    // config is read before grabbing the controller, so mistakes leave it untouched
+    let config_path = config_arg.clone().or_else(Config::find);
+    let mut config = match &config_path {
+        Some(path) => {
+            println!("Using config {}", path.display());
+            Config::load(path)?
+        }
+        None => Config::default(),
+    };
+    // edits from the curve editor always go to the user's own config
+    let save_path = config_arg.or_else(Config::default_path);
+    // without a config file, the driver picks one up once it's created
+    let watch_path = config_path.or_else(Config::default_path);
+
     let mut context = Context::new()?;
     let (mut device, mut handle) = open_device(&mut context, VID, PID).expect("Did not find USB device (if connected, perhaps you're not allowed to read from the device?)");
 
@@ -42,8 +188,13 @@ fn main() -> Result<()> {
 
     let endpoints = find_readable_endpoints(&mut device)?;
     let endpoint = endpoints
-        .first()
+        .iter()
+        .find(|e| e.address & rusb::constants::LIBUSB_ENDPOINT_IN != 0)
         .expect("No configurable endpoint found on device");
+    // rumble & lightbar are set through the interface's out endpoint
+    let out_endpoint = endpoints.iter().find(|e| {
+        e.iface == endpoint.iface && e.address & rusb::constants::LIBUSB_ENDPOINT_IN == 0
+    });
 
     println!("endpoints: {:?}", endpoints);
 
@@ -58,67 +209,54 @@ fn main() -> Result<()> {
     println!("Has kernel driver? {}", has_kernel_driver);
 
     // clam and configure device
-    configure_endpoint(&mut handle, &endpoint)?;
-
-    // Crate uinput device (/dev/input/jn, where n is a positive integer)
-    let u = UninitDevice::new().unwrap();
-    u.set_name("Sony Dualshock Hackery");
-    u.set_bustype(BusType::BUS_USB as u16);
-    u.set_vendor_id(VID);
-    u.set_product_id(PID);
-
-    u.enable_event_type(&EventType::EV_KEY)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_WEST), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_SOUTH), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_EAST), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_NORTH), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_SELECT), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_START), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_TOUCH), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_MODE), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_TL), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_TL2), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_TR), None)?;
-    u.enable_event_code(&EventCode::EV_KEY(EV_KEY::BTN_TR2), None)?;
-
-    let absinfo_dpad = AbsInfo {
-        value: 0,
-        minimum: -1,
-        maximum: 1,
-        fuzz: 0,
-        flat: 0,
-        resolution: 0,
-    };
-
-    let absinfo_stick = AbsInfo {
-        value: 127,
-        minimum: 0,
-        maximum: ANALOG_MAX as i32,
-        fuzz: 0,
-        flat: 15,
-        resolution: 0,
+    configure_endpoint(&mut handle, endpoint)?;
+
+    // the device can't change what it is while it exists, reloads keep the preset
+    let emulate = config.emulate;
+    // every profile's codes are enabled, so switching doesn't need a new device
+    let (capabilities, _) = capabilities(&config, emulate).split_desktop();
+    let v = create_device(&emulate.identity(), &capabilities)?;
+    // like the gamepad, the extra devices stay as they were created until a restart
+    let devices = config.devices.clone();
+    let touchpad = if devices.touchpad {
+        Some(create_device(&TOUCHPAD, &multitouch::capabilities())?)
+    } else {
+        None
     };
-    let absinfo_stick_r = AbsInfo {
-        value: 127,
-        minimum: 0,
-        maximum: ANALOG_MAX as i32,
-        fuzz: 0,
-        flat: 5,
-        resolution: 0,
+    let mut contacts = Contacts::new();
+    let motion = if devices.motion {
+        Some(create_device(&MOTION, &motion::capabilities())?)
+    } else {
+        None
     };
-    u.enable_event_type(&EventType::EV_ABS)?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_HAT0X), Some(&absinfo_dpad))?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_HAT0Y), Some(&absinfo_dpad))?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_TILT_X), Some(&absinfo_stick))?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_TILT_Y), Some(&absinfo_stick))?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_RX), Some(&absinfo_stick_r))?;
-    u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_RY), Some(&absinfo_stick_r))?;
-    // u.enable_event_code(&EventCode::EV_ABS(EV_ABS::ABS_RY), Some(&absinfo_stick))?;
+    let calibration = read_calibration(&handle, endpoint.iface).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read the motion sensor calibration, using the nominal one: {}",
+            e
+        );
+        Calibration::default()
+    });
+    let mut sensors = Sensors::new();
+    // the gyro bias is measured whenever the controller is put down, starting from the last one
+    let serial = read_serial(&handle, endpoint.iface);
+    let bias_path = Biases::default_path();
+    let mut biases = bias_path
+        .as_deref()
+        .map(|path| {
+            Biases::load(path).unwrap_or_else(|e| {
+                eprintln!("{:#}", e);
+                Biases::default()
+            })
+        })
+        .unwrap_or_default();
+    let saved_bias = serial
+        .as_ref()
+        .and_then(|serial| biases.0.get(serial).copied())
+        .unwrap_or_default();
+    let mut estimator = Estimator::new(&Stillness::default(), saved_bias);
 
     println!("Finished setting up virtual device");
 
-    let v = UInputDevice::create_from_device(&u)?;
-
     // Main loop
     let timeout = Duration::from_secs(1);
 
@@ -126,11 +264,182 @@ fn main() -> Result<()> {
     println!("01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64 TP PS\n");
     let mut buf: [u8; 64] = [0; 64];
 
-    let mut p_state = DS4State::initial_state();
+    let mut editor = tui::Editor::new();
+    let terminal = if tui {
+        Some(tui::Terminal::enter()?)
+    } else {
+        None
+    };
+    let mut last_draw = Instant::now();
+    // touchpad taps are timed from here
+    let start = Instant::now();
+
+    let mut active = config.default_profile_name().to_string();
+    let mut mapping = Mapping::new(&config.profiles[&active], emulate);
+    let mut desktop = Desktop::default();
+    desktop.update(&mapping)?;
+    // changing the mapping releases whatever the previous one held on the next report
+    let mut reporter = Reporter::new();
+    let mut switcher = Switcher::new(config.switch.clone());
+    let mut rumble_until = None;
+    // applications are only watched when a profile is meant for some
+    let mut watcher = process::Watcher::new(&active);
+    let mut processes = watch_processes(&config);
+    let reloads = watch_path.and_then(|path| match reload::watch(&path) {
+        Ok(reloads) => Some(reloads),
+        Err(e) => {
+            eprintln!("Not watching {} for changes: {}", path.display(), e);
+            None
+        }
+    });
+    send_feedback(
+        &handle,
+        out_endpoint,
+        &feedback(&config.profiles[&active], false),
+    )?;
+
     loop {
         handle.read_interrupt(endpoint.address, &mut buf, timeout)?;
         let event_time = event_time_now();
-        let c_state = DS4State::from(&buf);
+        let mut raw_state = DS4State::from(&buf);
+        calibration.apply(&mut raw_state);
+        if let Some(bias) = estimator.update(&raw_state) {
+            save_bias(&mut biases, bias_path.as_deref(), serial.as_deref(), bias);
+        }
+        estimator.apply(&mut raw_state);
+
+        let mut reloaded = None;
+        for result in reloads.iter().flat_map(Receiver::try_iter) {
+            match result {
+                Ok(new_config) => reloaded = Some(new_config),
+                Err(e) => notify(
+                    &mut editor,
+                    terminal.is_some(),
+                    vec![
+                        format!("{:#}", e),
+                        "Keeping the previous config".to_string(),
+                    ],
+                ),
+            }
+        }
+        if let Some(new_config) = reloaded {
+            // saving from the curve editor reloads what's already in use
+            if new_config != config {
+                let mut warnings = vec![];
+                if new_config.emulate != emulate {
+                    warnings.push("The virtual device can't change what it emulates while it runs, restart the driver to do so".to_string());
+                }
+                if new_config.devices != devices {
+                    warnings.push("Virtual devices can't be added or removed while the driver runs, restart it to do so".to_string());
+                }
+                let (new_capabilities, _) =
+                    self::capabilities(&new_config, emulate).split_desktop();
+                let missing = capabilities.missing(&new_capabilities);
+                if !missing.is_empty() {
+                    let names: Vec<_> = missing.iter().map(code_name).collect();
+                    warnings.push(format!(
+                        "{} can't be added to the virtual device while it runs, restart the driver to use them",
+                        names.join(", ")
+                    ));
+                }
+                let changed = capabilities.changed_ranges(&new_capabilities);
+                if !changed.is_empty() {
+                    let names: Vec<_> = changed.iter().map(|abs| format!("{:?}", abs)).collect();
+                    warnings.push(format!(
+                        "The ranges of {} (like a stick's `flat`) can't change while the driver runs, restart it to use the new ones",
+                        names.join(", ")
+                    ));
+                }
+                if new_config.auto_switch != config.auto_switch
+                    || has_rules(&new_config) != has_rules(&config)
+                {
+                    processes = watch_processes(&new_config);
+                }
+                if !new_config.profiles.contains_key(&active) {
+                    active = new_config.default_profile_name().to_string();
+                }
+                switcher = Switcher::new(new_config.switch.clone());
+                config = new_config;
+                mapping = Mapping::new(&config.profiles[&active], emulate);
+                send_feedback(
+                    &handle,
+                    out_endpoint,
+                    &feedback(&config.profiles[&active], false),
+                )?;
+                if terminal.is_some() {
+                    warnings.push("Reloaded config".to_string());
+                    notify(&mut editor, true, warnings);
+                } else {
+                    notify(&mut editor, false, warnings);
+                    println!("Reloaded config");
+                }
+            }
+        }
+
+        let mut target = None;
+        if let Some(direction) = switcher.update(&raw_state) {
+            let names = config.profiles.keys().map(String::as_str);
+            target = switch::cycle(names, &active, direction).map(str::to_string);
+        }
+        if let Some(processes) = processes.as_ref().and_then(|r| r.try_iter().last()) {
+            target = target.or_else(|| {
+                watcher.update(&config.profiles, config.default_profile_name(), &processes)
+            });
+        }
+        if let Some(next) = target {
+            if next != active {
+                active = next;
+                mapping = Mapping::new(&config.profiles[&active], emulate);
+                editor.set_status(format!("Switched to profile {}", active));
+                send_feedback(
+                    &handle,
+                    out_endpoint,
+                    &feedback(&config.profiles[&active], true),
+                )?;
+                rumble_until = Some(Instant::now() + SWITCH_RUMBLE_TIME);
+            }
+        }
+        if rumble_until.is_some_and(|until| Instant::now() >= until) {
+            send_feedback(
+                &handle,
+                out_endpoint,
+                &feedback(&config.profiles[&active], false),
+            )?;
+            rumble_until = None;
+        }
+        let mut c_state = raw_state;
+        switcher.mask(&mut c_state);
+
+        if terminal.is_some() {
+            let mut quit = false;
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()? {
+                    let profile = config
+                        .profiles
+                        .get_mut(&active)
+                        .expect("active profile exists");
+                    match editor.handle(key, profile) {
+                        tui::Action::Changed => mapping = Mapping::new(profile, emulate),
+                        tui::Action::Save => match &save_path {
+                            Some(path) => match config.save(path) {
+                                Ok(()) => editor.set_status(format!("Saved to {}", path.display())),
+                                Err(e) => editor.set_status(format!("{:#}", e)),
+                            },
+                            None => editor.set_status("No config location, is $HOME set?"),
+                        },
+                        tui::Action::Quit => quit = true,
+                        tui::Action::None => (),
+                    }
+                }
+            }
+            if quit {
+                break;
+            }
+            if last_draw.elapsed() >= TUI_FRAME_TIME {
+                editor.draw(&mut stdout, &config.profiles[&active], &c_state)?;
+                last_draw = Instant::now();
+            }
+        }
 
         // DEBUG OUTPUT
         // print!("\r{}", c_state);
@@ -147,185 +456,384 @@ fn main() -> Result<()> {
         // print!(" {:02X}", touchpad_down);
         // print!(" {:02X}", ps_button);
 
-        // TODO: check c_state to p_state to see if changes happened, before making c_state become p_state
-
-        // DPAD
-        if c_state.up != p_state.up || c_state.down != p_state.down {
-            let mut val = 0;
-            if c_state.up {
-                val = -1
-            } else if c_state.down {
-                val = 1;
-            }
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_ABS(EV_ABS::ABS_HAT0Y),
-                value: val,
-            })?;
+        desktop.update(&mapping)?;
+        let mut sink = VirtualDevices {
+            gamepad: &v,
+            desktop: desktop.device.as_ref(),
+            time: event_time,
+        };
+        reporter.emit(&mapping.frame(&c_state, start.elapsed()), &mut sink)?;
+        if let Some(touchpad) = &touchpad {
+            let events = contacts.report(&c_state.touches, c_state.touchpad);
+            write_report(touchpad, event_time, &events)?;
         }
-        if c_state.left != p_state.left || c_state.right != p_state.right {
-            let mut val = 0;
-            if c_state.left {
-                val = -1
-            } else if c_state.right {
-                val = 1;
-            }
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_ABS(EV_ABS::ABS_HAT0X),
-                value: val,
-            })?;
+        if let Some(motion) = &motion {
+            write_report(motion, event_time, &sensors.report(&c_state))?;
         }
 
-        // Face buttons
-        if c_state.square != p_state.square {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_WEST),
-                value: c_state.square as i32,
-            })?;
-        }
-        if c_state.cross != p_state.cross {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_SOUTH),
-                value: c_state.cross as i32,
-            })?;
-        }
-        if c_state.circle != p_state.circle {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_EAST),
-                value: c_state.circle as i32,
-            })?;
+        stdout.flush().unwrap();
+    }
+
+    // cleanup after use
+    drop(terminal);
+    handle.release_interface(endpoint.iface)?;
+    if has_kernel_driver {
+        handle.attach_kernel_driver(endpoint.iface)?;
+    }
+    Ok(())
+}
+
+fn feedback(profile: &Profile, rumble: bool) -> Feedback {
+    let strength = if rumble { 0x80 } else { 0 };
+    Feedback {
+        rumble_weak: strength,
+        rumble_strong: strength,
+        lightbar: profile.lightbar,
+    }
+}
+
+fn send_feedback<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    endpoint: Option<&Endpoint>,
+    feedback: &Feedback,
+) -> Result<()> {
+    // without an out endpoint there's no feedback, but the controller still works
+    if let Some(endpoint) = endpoint {
+        handle.write_interrupt(endpoint.address, &feedback.report(), Duration::from_secs(1))?;
+    }
+    Ok(())
+}
+
+/// Whether a profile of `config` is meant for some applications
+fn has_rules(config: &Config) -> bool {
+    config
+        .profiles
+        .values()
+        .any(|profile| !profile.rule.is_empty())
+}
+
+/// Running applications, listed in the background, when `config` has profiles meant for some.
+/// Dropping the receiver stops the listing.
+fn watch_processes(config: &Config) -> Option<Receiver<Vec<process::Process>>> {
+    has_rules(config).then(|| {
+        let interval = Duration::from_millis(config.auto_switch.interval_ms);
+        match &config.auto_switch.hook {
+            Some(hook) => process::spawn(process::Hook::new(hook), interval),
+            None => process::spawn(process::Procfs::new(), interval),
         }
-        if c_state.triangle != p_state.triangle {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_NORTH),
-                value: c_state.triangle as i32,
-            })?;
+    })
+}
+
+/// Show `lines` in the status line of the curve editor while it's open, which printing would
+/// garble, or else print them
+fn notify(editor: &mut tui::Editor, tui: bool, lines: Vec<String>) {
+    if tui {
+        editor.set_status(lines.join("\n").replace('\n', "  "));
+    } else {
+        for line in lines {
+            eprintln!("{}", line);
         }
+    }
+}
+
+/// Codes of every profile of `config`, for a device emulating `preset`
+fn capabilities(config: &Config, preset: Preset) -> Capabilities {
+    let mut capabilities = Capabilities::default();
+    for profile in config.profiles.values() {
+        capabilities.extend(Mapping::new(profile, preset).capabilities());
+    }
+    capabilities
+}
 
-        // Triggers
-        if c_state.l1 != p_state.l1 {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_TL),
-                value: c_state.l1 as i32,
-            })?;
+/// Read feature report `id` of the controller into `buf`, returning its length
+fn get_feature_report<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    iface: u8,
+    id: u8,
+    buf: &mut [u8],
+) -> rusb::Result<usize> {
+    // HID class GET_REPORT request, of a feature report
+    let request_type = rusb::request_type(
+        rusb::Direction::In,
+        rusb::RequestType::Class,
+        rusb::Recipient::Interface,
+    );
+    let value = 0x0300 | id as u16;
+    handle.read_control(
+        request_type,
+        0x01,
+        value,
+        iface as u16,
+        buf,
+        Duration::from_secs(1),
+    )
+}
+
+/// Motion sensor calibration of the controller, from its feature report
+fn read_calibration<T: UsbContext>(handle: &DeviceHandle<T>, iface: u8) -> Result<Calibration> {
+    let mut buf = [0; motion::CALIBRATION_REPORT_LEN];
+    let len = get_feature_report(handle, iface, motion::CALIBRATION_REPORT, &mut buf)?;
+    Calibration::from_report(&buf[..len]).context("The calibration report is too short")
+}
+
+/// Serial number of the controller, or its Bluetooth address when it has none
+fn read_serial<T: UsbContext>(handle: &DeviceHandle<T>, iface: u8) -> Option<String> {
+    let timeout = Duration::from_secs(1);
+    let serial = handle.read_languages(timeout).ok().and_then(|languages| {
+        let device_desc = handle.device().device_descriptor().ok()?;
+        handle
+            .read_serial_number_string(*languages.first()?, &device_desc, timeout)
+            .ok()
+    });
+    if let Some(serial) = serial.filter(|serial| !serial.is_empty()) {
+        return Some(serial);
+    }
+    // like hid-sony, the address is read from a feature report, lowest byte first
+    let mut buf = [0; 7];
+    let len = get_feature_report(handle, iface, 0x81, &mut buf).ok()?;
+    if len < buf.len() {
+        return None;
+    }
+    let address: Vec<_> = buf[1..]
+        .iter()
+        .rev()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Some(address.join(":"))
+}
+
+/// Save `bias` as the one of controller `serial`, when it changed enough since it was saved
+fn save_bias(biases: &mut Biases, path: Option<&Path>, serial: Option<&str>, bias: [f32; 3]) {
+    let (Some(path), Some(serial)) = (path, serial) else {
+        return;
+    };
+    let saved = biases.0.get(serial).copied().unwrap_or_default();
+    let changed = saved
+        .iter()
+        .zip(bias)
+        .any(|(saved, bias)| (saved - bias).abs() >= BIAS_SAVE_CHANGE);
+    if !changed {
+        return;
+    }
+    biases.0.insert(serial.to_string(), bias);
+    if let Err(e) = biases.save(path) {
+        eprintln!("{:#}", e);
+    }
+}
+
+/// Write `events` to `device` as one report, if there are any
+fn write_report(device: &UInputDevice, time: TimeVal, events: &[(EventCode, i32)]) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let report = (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+    for (event_code, value) in events.iter().chain([&report]) {
+        device.write_event(&InputEvent {
+            time,
+            event_code: *event_code,
+            value: *value,
+        })?;
+    }
+    Ok(())
+}
+
+/// Create a uinput device (/dev/input/eventN) reporting `capabilities`
+fn create_device(identity: &Identity, capabilities: &Capabilities) -> Result<UInputDevice> {
+    let u = UninitDevice::new().context("Could not create a virtual device")?;
+    u.set_name(identity.name);
+    u.set_bustype(identity.bustype as u16);
+    u.set_vendor_id(identity.vendor);
+    u.set_product_id(identity.product);
+    u.set_version(identity.version);
+
+    for prop in &capabilities.props {
+        u.enable_property(prop)?;
+    }
+    if !capabilities.keys.is_empty() {
+        u.enable_event_type(&EventType::EV_KEY)?;
+        for key in &capabilities.keys {
+            u.enable_event_code(&EventCode::EV_KEY(*key), None)?;
         }
-        if c_state.l2 != p_state.l2 {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_TL2),
-                value: c_state.l2 as i32,
-            })?;
+    }
+    if !capabilities.abs.is_empty() {
+        u.enable_event_type(&EventType::EV_ABS)?;
+        for (code, range) in &capabilities.abs {
+            let absinfo = AbsInfo {
+                value: range.rest,
+                minimum: range.minimum,
+                maximum: range.maximum,
+                fuzz: range.fuzz,
+                flat: range.flat,
+                resolution: range.resolution,
+            };
+            u.enable_event_code(&EventCode::EV_ABS(*code), Some(&absinfo))?;
         }
-        if c_state.r1 != p_state.r1 {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_TR),
-                value: c_state.r1 as i32,
-            })?;
+    }
+    if !capabilities.rel.is_empty() {
+        u.enable_event_type(&EventType::EV_REL)?;
+        for rel in &capabilities.rel {
+            u.enable_event_code(&EventCode::EV_REL(*rel), None)?;
         }
-        if c_state.r2 != p_state.r2 {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_TR2),
-                value: c_state.r2 as i32,
-            })?;
+    }
+    if !capabilities.msc.is_empty() {
+        u.enable_event_type(&EventType::EV_MSC)?;
+        for msc in &capabilities.msc {
+            u.enable_event_code(&EventCode::EV_MSC(*msc), None)?;
         }
+    }
+    Ok(UInputDevice::create_from_device(&u)?)
+}
 
-        // PS & Touchpad
-        if c_state.ps != p_state.ps {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_MODE),
-                value: c_state.ps as i32,
-            })?;
-        }
-        if c_state.touchpad != p_state.touchpad {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_TOUCH),
-                value: c_state.touchpad as i32,
-            })?;
-        }
+fn code_name(code: &EventCode) -> String {
+    match code {
+        EventCode::EV_KEY(key) => format!("{:?}", key),
+        EventCode::EV_ABS(abs) => format!("{:?}", abs),
+        EventCode::EV_REL(rel) => format!("{:?}", rel),
+        code => format!("{:?}", code),
+    }
+}
 
-        // start select
-        if c_state.start != p_state.start {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_START),
-                value: c_state.start as i32,
-            })?;
-        }
-        if c_state.select != p_state.select {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_KEY(EV_KEY::BTN_SELECT),
-                value: c_state.select as i32,
-            })?;
+fn calibrate() -> Result<()> {
+    let path = Biases::default_path().context("No place to save the gyro bias, is $HOME set?")?;
+    let mut context = Context::new()?;
+    let (mut device, mut handle) = open_device(&mut context, VID, PID)
+        .context("Did not find USB device (if connected, perhaps you're not allowed to read from the device?)")?;
+    let endpoints = find_readable_endpoints(&mut device)?;
+    let endpoint = endpoints
+        .iter()
+        .find(|e| e.address & rusb::constants::LIBUSB_ENDPOINT_IN != 0)
+        .context("No configurable endpoint found on device")?;
+    let has_kernel_driver = handle.kernel_driver_active(endpoint.iface) == Ok(true);
+    if has_kernel_driver {
+        handle.detach_kernel_driver(endpoint.iface)?;
+    }
+    let measured = configure_endpoint(&mut handle, endpoint)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| measure_bias(&handle, endpoint));
+    // the kernel gets the controller back however measuring went
+    let released = handle.release_interface(endpoint.iface);
+    if has_kernel_driver {
+        handle.attach_kernel_driver(endpoint.iface)?;
+    }
+    let (serial, bias) = measured?;
+    released?;
+    let mut biases = Biases::load(&path)?;
+    biases.0.insert(serial.clone(), bias);
+    biases.save(&path)?;
+    println!(
+        "Gyro bias of {}: {:.2} {:.2} {:.2} degrees per second, saved to {}",
+        serial,
+        bias[0],
+        bias[1],
+        bias[2],
+        path.display()
+    );
+    Ok(())
+}
+
+/// Serial number & gyro bias of the controller lying still, read from its claimed `endpoint`
+fn measure_bias<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    endpoint: &Endpoint,
+) -> Result<(String, [f32; 3])> {
+    let serial = read_serial(handle, endpoint.iface)
+        .context("Could not read the controller's serial number")?;
+    let calibration = read_calibration(handle, endpoint.iface).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not read the motion sensor calibration, using the nominal one: {}",
+            e
+        );
+        Calibration::default()
+    });
+
+    println!("Place the controller on a table and don't touch it");
+    // a longer look at it than while playing, which averages out more noise
+    let stillness = Stillness {
+        time: Duration::from_secs(3),
+        ..Stillness::default()
+    };
+    let mut estimator = Estimator::new(&stillness, [0.0; 3]);
+    let mut buf = [0; 64];
+    let start = Instant::now();
+    while start.elapsed() < CALIBRATE_TIMEOUT {
+        handle.read_interrupt(endpoint.address, &mut buf, Duration::from_secs(1))?;
+        let mut state = DS4State::from(&buf);
+        calibration.apply(&mut state);
+        if let Some(bias) = estimator.update(&state) {
+            return Ok((serial, bias));
         }
+    }
+    bail!("The controller didn't lie still, try again on a steady table")
+}
 
-        // Analogues
-        // Left stick
-        if c_state.lsx != p_state.lsx {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_ABS(EV_ABS::ABS_TILT_X),
-                value: curve::custom(c_state.lsx, ANALOG_MAX) as i32,
-            })?;
+fn check_config(file: Option<PathBuf>) -> Result<()> {
+    let path = match file.or_else(Config::find) {
+        Some(path) => path,
+        None => {
+            println!("No config file found, the built in defaults are used");
+            return Ok(());
         }
-        if c_state.lsy != p_state.lsy {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_ABS(EV_ABS::ABS_TILT_Y),
-                value: curve::custom(c_state.lsy, ANALOG_MAX) as i32,
-            })?;
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Could not read config {}", path.display()))?;
+    match Config::parse(&content) {
+        Ok(_) => {
+            println!("{}: ok", path.display());
+            Ok(())
         }
-        // Right stick
-        if c_state.rsx != p_state.rsx {
-            // Deadzone check
-            if i16::abs(c_state.rsx as i16 - 128) > 100 {
-                v.write_event(&InputEvent {
-                    time: event_time,
-                    event_code: EventCode::EV_ABS(EV_ABS::ABS_RX),
-                    value: c_state.rsx as i32,
-                })?;
-            } else {
-                v.write_event(&InputEvent {
-                    time: event_time,
-                    event_code: EventCode::EV_ABS(EV_ABS::ABS_RX),
-                    value: 127, // center stick value
-                })?;
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}:{}", path.display(), diagnostic);
             }
+            std::process::exit(1);
         }
-        if c_state.rsy != p_state.rsy {
-            v.write_event(&InputEvent {
-                time: event_time,
-                event_code: EventCode::EV_ABS(EV_ABS::ABS_RY),
-                value: c_state.rsy as i32,
-            })?;
-        }
+    }
+}
 
-        // Needs to be called to make written events be updated
-        v.write_event(&InputEvent {
-            time: event_time,
-            event_code: EventCode::EV_SYN(EV_SYN::SYN_REPORT),
-            value: 0,
-        })?;
+fn dump_config(file: Option<PathBuf>, resolved: bool) -> Result<()> {
+    let config = match file.or_else(Config::find) {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    let dump = if resolved {
+        config.to_resolved_toml()?
+    } else {
+        config.to_toml()?
+    };
+    print!("{}", dump);
+    Ok(())
+}
 
-        p_state = c_state;
-        stdout.flush().unwrap();
+fn import_config(file: &Path, name: Option<String>) -> Result<()> {
+    let xml = fs::read_to_string(file)
+        .with_context(|| format!("Could not read profile {}", file.display()))?;
+    let import =
+        import::ds4windows(&xml).with_context(|| format!("Could not import {}", file.display()))?;
+    let name = name
+        .or_else(|| Some(file.file_stem()?.to_str()?.to_string()))
+        .unwrap_or_else(|| "imported".to_string());
+
+    let config = Config {
+        profiles: BTreeMap::from([(name, import.profile)]),
+        ..Config::default()
+    };
+    for setting in &import.unsupported {
+        eprintln!("No equivalent for {}", setting);
+    }
+    for problem in validate::validate(&config) {
+        eprintln!("{}: {}", problem.key.join("."), problem.message);
     }
 
-    // cleanup after use
-    handle.release_interface(endpoint.iface)?;
-    if has_kernel_driver {
-        handle.attach_kernel_driver(endpoint.iface)?;
+    #[derive(Serialize)]
+    struct Profiles<'a> {
+        profiles: &'a BTreeMap<String, Profile>,
     }
+    print!(
+        "{}",
+        toml::to_string_pretty(&Profiles {
+            profiles: &config.profiles
+        })?
+    );
     Ok(())
 }
 
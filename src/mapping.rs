@@ -0,0 +1,1515 @@
+use std::{fmt::Display, io, str::FromStr, time::Duration};
+
+use evdev_rs::enums::{EventCode, InputProp, EV_ABS, EV_KEY, EV_MSC, EV_REL};
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    curve::Curve,
+    flick::Flick,
+    gesture::{Gesture, Recognizer},
+    gyro::{self, Aim},
+    hid::{Button, DS4State, ANALOG_MAX},
+    movement::{Detector, Movement},
+    preset::Preset,
+    profile::{code, Axis, Profile},
+    tilt::Steering,
+    touchpad::{self, Mode, Stick, Trackpad},
+};
+
+/// Analog inputs of the controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Analog {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    L2,
+    R2,
+}
+
+impl Analog {
+    fn is_stick(&self) -> bool {
+        !matches!(self, Analog::L2 | Analog::R2)
+    }
+}
+
+/// Direction along a stick axis, negative being left or up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Half {
+    Negative,
+    Positive,
+}
+
+/// How a touchpad region is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Contact {
+    /// The pad is clicked with a finger in the region
+    Click,
+    /// A finger is in the region
+    Touch,
+}
+
+/// Controller input a [`Binding`] reads, named in config files as listed in [`Input::NAMES`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Input {
+    /// Button, or direction of the dpad (which is a hat)
+    Button(Button),
+    /// Full range of an analog stick axis or trigger
+    Analog(Analog),
+    /// One direction of a stick axis, from center to full deflection
+    Half(Analog, Half),
+    /// Touchpad region by its 1-based number, see [`crate::touchpad::Regions`], named like
+    /// `region1_click` & `region1_touch`
+    Region(u8, Contact),
+    /// Touchpad gesture, held for the report it completes in, named as in [`Gesture::NAMES`]
+    Gesture(Gesture),
+    /// Motion gesture, held for the report it completes in, named as in [`Movement::NAMES`]
+    Movement(Movement),
+}
+
+impl Input {
+    /// Names of the inputs which aren't buttons, buttons are named as in [`Button`]
+    pub const NAMES: [(&'static str, Input); 14] = [
+        ("left_x", Input::Analog(Analog::LeftX)),
+        ("left_y", Input::Analog(Analog::LeftY)),
+        ("right_x", Input::Analog(Analog::RightX)),
+        ("right_y", Input::Analog(Analog::RightY)),
+        ("l2_analog", Input::Analog(Analog::L2)),
+        ("r2_analog", Input::Analog(Analog::R2)),
+        (
+            "left_stick_left",
+            Input::Half(Analog::LeftX, Half::Negative),
+        ),
+        (
+            "left_stick_right",
+            Input::Half(Analog::LeftX, Half::Positive),
+        ),
+        ("left_stick_up", Input::Half(Analog::LeftY, Half::Negative)),
+        (
+            "left_stick_down",
+            Input::Half(Analog::LeftY, Half::Positive),
+        ),
+        (
+            "right_stick_left",
+            Input::Half(Analog::RightX, Half::Negative),
+        ),
+        (
+            "right_stick_right",
+            Input::Half(Analog::RightX, Half::Positive),
+        ),
+        (
+            "right_stick_up",
+            Input::Half(Analog::RightY, Half::Negative),
+        ),
+        (
+            "right_stick_down",
+            Input::Half(Analog::RightY, Half::Positive),
+        ),
+    ];
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some((_, input)) = Input::NAMES.iter().find(|(n, _)| *n == name) {
+            return Ok(*input);
+        }
+        if let Some((_, gesture)) = Gesture::NAMES.iter().find(|(n, _)| *n == name) {
+            return Ok(Input::Gesture(*gesture));
+        }
+        if let Some((_, movement)) = Movement::NAMES.iter().find(|(n, _)| *n == name) {
+            return Ok(Input::Movement(*movement));
+        }
+        if let Some(region) = name.strip_prefix("region") {
+            let (number, contact) = match region.split_once('_') {
+                Some((number, "click")) => (number, Contact::Click),
+                Some((number, "touch")) => (number, Contact::Touch),
+                _ => return Err(format!("unknown input `{}`", name)),
+            };
+            return match number.parse() {
+                Ok(number) if number > 0 => Ok(Input::Region(number, contact)),
+                _ => Err(format!("unknown input `{}`", name)),
+            };
+        }
+        code::parse::<Button>(name)
+            .map(Input::Button)
+            .ok_or_else(|| format!("unknown input `{}`", name))
+    }
+}
+
+impl Display for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // every button name is a single lowercase word
+            Input::Button(button) => write!(f, "{}", format!("{:?}", button).to_lowercase()),
+            Input::Region(number, contact) => {
+                let contact = format!("{:?}", contact).to_lowercase();
+                write!(f, "region{}_{}", number, contact)
+            }
+            Input::Gesture(gesture) => write!(f, "{}", gesture.name()),
+            Input::Movement(movement) => write!(f, "{}", movement.name()),
+            input => {
+                let (name, _) = Input::NAMES
+                    .iter()
+                    .find(|(_, i)| i == input)
+                    .expect("every analog input is named");
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+/// Event a [`Binding`] reports
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Output {
+    None,
+    Key(EV_KEY),
+    /// Keys pressed together in order & released together, written as `KEY_LEFTCTRL+KEY_TAB`
+    Combo(Vec<EV_KEY>),
+    Abs(EV_ABS),
+    Rel(EV_REL),
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let output = match name {
+            "none" => Some(Output::None),
+            _ if name.contains('+') => name
+                .split('+')
+                .map(|key| match key.trim().parse() {
+                    Ok(Output::Key(key)) => Some(key),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Output::Combo),
+            _ if name.starts_with("KEY_") || name.starts_with("BTN_") => {
+                code::parse(name).map(Output::Key)
+            }
+            _ if name.starts_with("ABS_") => code::parse(name).map(Output::Abs),
+            _ if name.starts_with("REL_") => code::parse(name).map(Output::Rel),
+            _ => None,
+        };
+        output.ok_or_else(|| format!("unknown event code `{}`", name))
+    }
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::None => write!(f, "none"),
+            Output::Key(code) => write!(f, "{:?}", code),
+            Output::Combo(keys) => {
+                let names: Vec<_> = keys.iter().map(|key| format!("{:?}", key)).collect();
+                write!(f, "{}", names.join("+"))
+            }
+            Output::Abs(code) => write!(f, "{:?}", code),
+            Output::Rel(code) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// What an input is reported as. Written as just the code name in config files, or as a table
+/// when any of the other settings differ from their default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub output: Output,
+    /// Value reported while a button is held for absolute outputs, or once per press for relative
+    /// outputs, like a notch of a mouse wheel
+    pub value: i32,
+    /// Fraction of an analog input's range from which it's held, for key outputs
+    pub threshold: f32,
+    /// Motion per report at full deflection of an analog input, for relative outputs
+    pub speed: f32,
+    /// Acceleration of an analog input, for relative outputs
+    pub curve: Curve,
+}
+
+impl Binding {
+    pub fn new(output: Output) -> Self {
+        Self {
+            output,
+            value: 1,
+            threshold: 0.5,
+            speed: 10.0,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn with_value(output: Output, value: i32) -> Self {
+        Self {
+            value,
+            ..Self::new(output)
+        }
+    }
+}
+
+/// Full form of a binding in config files
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingTable {
+    code: String,
+    #[serde(default = "default_value")]
+    value: i32,
+    #[serde(default = "default_threshold")]
+    threshold: f32,
+    #[serde(default = "default_speed")]
+    speed: f32,
+    #[serde(default)]
+    curve: Curve,
+}
+
+fn default_value() -> i32 {
+    Binding::new(Output::None).value
+}
+
+fn default_threshold() -> f32 {
+    Binding::new(Output::None).threshold
+}
+
+fn default_speed() -> f32 {
+    Binding::new(Output::None).speed
+}
+
+impl Serialize for Binding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if *self == Binding::new(self.output.clone()) {
+            return serializer.collect_str(&self.output);
+        }
+        BindingTable {
+            code: self.output.to_string(),
+            value: self.value,
+            threshold: self.threshold,
+            speed: self.speed,
+            curve: self.curve.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BindingVisitor;
+
+        impl<'de> Visitor<'de> for BindingVisitor {
+            type Value = Binding;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "an event code, or a table with a `code`")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Binding, E> {
+                name.parse().map(Binding::new).map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Binding, A::Error> {
+                let table = BindingTable::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(Binding {
+                    output: table.code.parse().map_err(de::Error::custom)?,
+                    value: table.value,
+                    threshold: table.threshold,
+                    speed: table.speed,
+                    curve: table.curve,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(BindingVisitor)
+    }
+}
+
+impl Serialize for Input {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Range of an absolute axis on the virtual device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbsRange {
+    pub minimum: i32,
+    pub maximum: i32,
+    /// Value while nothing bound to the axis is held
+    pub rest: i32,
+    /// Noise the input subsystem filters out of changes
+    pub fuzz: i32,
+    pub flat: i32,
+    /// Units per millimeter, or per unit of what the axis measures, `0` when unknown
+    pub resolution: i32,
+}
+
+/// Event codes a virtual device needs to report what mappings output
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub keys: Vec<EV_KEY>,
+    pub abs: Vec<(EV_ABS, AbsRange)>,
+    pub rel: Vec<EV_REL>,
+    pub msc: Vec<EV_MSC>,
+    /// What kind of device it is, see linux/input-event-codes.h
+    pub props: Vec<InputProp>,
+}
+
+impl Capabilities {
+    fn add_keys(&mut self, keys: &[EV_KEY]) {
+        for key in keys {
+            if !self.keys.contains(key) {
+                self.keys.push(*key);
+            }
+        }
+    }
+
+    /// Split off the keyboard keys, mouse buttons and relative axes, which keyboards & mice
+    /// rather than gamepads report
+    pub fn split_desktop(mut self) -> (Capabilities, Capabilities) {
+        let desktop = Capabilities {
+            keys: self
+                .keys
+                .iter()
+                .copied()
+                .filter(|key| is_desktop(*key))
+                .collect(),
+            abs: vec![],
+            rel: std::mem::take(&mut self.rel),
+            msc: vec![],
+            props: vec![],
+        };
+        self.keys.retain(|key| !is_desktop(*key));
+        (self, desktop)
+    }
+
+    /// Add the codes of `other`, keeping the range of axes which are already known
+    pub fn extend(&mut self, other: &Capabilities) {
+        for key in &other.keys {
+            if !self.keys.contains(key) {
+                self.keys.push(*key);
+            }
+        }
+        for (code, range) in &other.abs {
+            if self.range(*code).is_none() {
+                self.abs.push((*code, *range));
+            }
+        }
+        for rel in &other.rel {
+            if !self.rel.contains(rel) {
+                self.rel.push(*rel);
+            }
+        }
+        for msc in &other.msc {
+            if !self.msc.contains(msc) {
+                self.msc.push(*msc);
+            }
+        }
+        for prop in &other.props {
+            if !self.props.contains(prop) {
+                self.props.push(*prop);
+            }
+        }
+    }
+
+    pub fn range(&self, code: EV_ABS) -> Option<&AbsRange> {
+        self.abs.iter().find(|(c, _)| *c == code).map(|(_, r)| r)
+    }
+
+    /// Codes of `other` which aren't part of these
+    pub fn missing(&self, other: &Capabilities) -> Vec<EventCode> {
+        let keys = other
+            .keys
+            .iter()
+            .filter(|key| !self.keys.contains(key))
+            .map(|key| EventCode::EV_KEY(*key));
+        let abs = other
+            .abs
+            .iter()
+            .filter(|(code, _)| self.range(*code).is_none())
+            .map(|(code, _)| EventCode::EV_ABS(*code));
+        let rel = other
+            .rel
+            .iter()
+            .filter(|rel| !self.rel.contains(rel))
+            .map(|rel| EventCode::EV_REL(*rel));
+        keys.chain(abs).chain(rel).collect()
+    }
+
+    /// Axes of `other` which are part of these, but in another range, like a stick's new `flat`
+    pub fn changed_ranges(&self, other: &Capabilities) -> Vec<EV_ABS> {
+        other
+            .abs
+            .iter()
+            .filter(|(code, range)| self.range(*code).is_some_and(|known| known != range))
+            .map(|(code, _)| *code)
+            .collect()
+    }
+}
+
+/// Whether `key` is a keyboard key (`KEY_*`) or mouse button rather than a gamepad button
+pub fn is_desktop(key: EV_KEY) -> bool {
+    let mouse = EV_KEY::BTN_LEFT as u32..=EV_KEY::BTN_TASK as u32;
+    format!("{:?}", key).starts_with("KEY_") || mouse.contains(&(key as u32))
+}
+
+/// Value of a key or absolute axis code, and the value it returns to when released
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Value {
+    pub code: EventCode,
+    pub value: i32,
+    pub rest: i32,
+}
+
+/// Everything a mapping reports for one controller state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+    /// Value of every key & absolute axis code the mapping outputs, and the steps of relative
+    /// axes bound to buttons
+    pub values: Vec<Value>,
+    /// Relative motion of analog inputs, which unlike values is reported every frame. Fractions
+    /// add up over frames.
+    pub motion: Vec<(EV_REL, f32)>,
+}
+
+/// How a held input is read
+enum Level {
+    Digital(bool),
+    Analog {
+        /// Value in the range of the controller
+        value: i32,
+        rest: i32,
+        /// Distance from `rest`, `-1.0` to `1.0`
+        offset: f32,
+    },
+}
+
+/// Bindings of the `buttons` & `axes` tables of `profile`, which its `map` replaces
+pub fn table_bindings(profile: &Profile) -> Vec<(Input, Binding)> {
+    let axes = &profile.axes;
+    let mut bindings: Vec<(Input, Binding)> = Button::ALL
+        .iter()
+        .filter_map(|button| {
+            let code = profile.buttons.code(*button)?;
+            Some((Input::Button(*button), Binding::new(Output::Key(code))))
+        })
+        .collect();
+    bindings.extend([
+        (
+            Input::Button(Button::Up),
+            Binding::with_value(Output::Abs(axes.dpad_y), -1),
+        ),
+        (
+            Input::Button(Button::Down),
+            Binding::with_value(Output::Abs(axes.dpad_y), 1),
+        ),
+        (
+            Input::Button(Button::Left),
+            Binding::with_value(Output::Abs(axes.dpad_x), -1),
+        ),
+        (
+            Input::Button(Button::Right),
+            Binding::with_value(Output::Abs(axes.dpad_x), 1),
+        ),
+        (
+            Input::Analog(Analog::LeftX),
+            Binding::new(Output::Abs(axes.left_x)),
+        ),
+        (
+            Input::Analog(Analog::LeftY),
+            Binding::new(Output::Abs(axes.left_y)),
+        ),
+        (
+            Input::Analog(Analog::RightX),
+            Binding::new(Output::Abs(axes.right_x)),
+        ),
+        (
+            Input::Analog(Analog::RightY),
+            Binding::new(Output::Abs(axes.right_y)),
+        ),
+    ]);
+    bindings
+}
+
+/// Bindings of every controller input of a profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    bindings: Vec<(Input, Binding)>,
+    sticks: [Axis; 4],
+    /// Flat of the left & right stick in the reported range
+    flats: [i32; 2],
+    /// Range reported for the full stick axes
+    stick_range: AbsRange,
+    /// Mouse input of the touchpad, when it's used as a trackpad
+    trackpad: Option<Trackpad>,
+    /// Axes & deflection of the touchpad, when it's used as a stick
+    touch_stick: Option<(EV_ABS, EV_ABS, Stick)>,
+    /// Rectangle of every touchpad region
+    regions: Vec<[f32; 4]>,
+    recognizer: Recognizer,
+    detector: Detector,
+    /// Gyro aiming, and the right stick axes it deflects in stick mode
+    aim: Option<(Aim, EV_ABS, EV_ABS)>,
+    /// Mouse turning of the right stick, when it's a flick stick
+    flick: Option<Flick>,
+    /// Tilt steering, and the axis it deflects
+    steering: Option<(Steering, EV_ABS)>,
+    /// Codes the bindings & processors output
+    capabilities: Capabilities,
+}
+
+impl Mapping {
+    /// Bindings of `profile`: those of its `buttons` & `axes` tables, replaced by its `map`.
+    /// Sticks are reported in the range of `preset`. A trackpad's click is a left click, and a
+    /// flick stick isn't reported as a stick, unless `map` binds them.
+    pub fn new(profile: &Profile, preset: Preset) -> Self {
+        let mut bindings = table_bindings(profile);
+        let axes = &profile.axes;
+        let trackpad = (profile.touchpad.mode == Mode::Trackpad).then(|| {
+            bindings.retain(|(input, _)| *input != Input::Button(Button::Touchpad));
+            bindings.push((
+                Input::Button(Button::Touchpad),
+                Binding::new(Output::Key(EV_KEY::BTN_LEFT)),
+            ));
+            Trackpad::new(&profile.touchpad)
+        });
+        let flick = profile.flick_stick.enabled.then(|| {
+            bindings.retain(|(input, _)| {
+                *input != Input::Analog(Analog::RightX) && *input != Input::Analog(Analog::RightY)
+            });
+            Flick::new(&profile.flick_stick)
+        });
+        for (input, binding) in &profile.map {
+            bindings.retain(|(i, _)| i != input);
+            bindings.push((*input, binding.clone()));
+        }
+        let mut mapping = Self {
+            bindings,
+            sticks: [
+                profile.left_stick.x.clone(),
+                profile.left_stick.y.clone(),
+                profile.right_stick.x.clone(),
+                profile.right_stick.y.clone(),
+            ],
+            flats: [profile.left_stick.flat, profile.right_stick.flat]
+                .map(|flat| preset.stick_flat(flat)),
+            stick_range: preset.stick_range(),
+            trackpad,
+            touch_stick: (profile.touchpad.mode == Mode::Stick).then(|| {
+                let stick = &profile.touchpad.stick;
+                (stick.x, stick.y, Stick::new(stick))
+            }),
+            regions: profile.touchpad.regions.rects(),
+            recognizer: Recognizer::new(&profile.touchpad.gestures),
+            detector: Detector::new(&profile.movements),
+            aim: (profile.gyro.mode != gyro::Mode::Off)
+                .then(|| (Aim::new(&profile.gyro), axes.right_x, axes.right_y)),
+            flick,
+            steering: profile
+                .tilt
+                .enabled
+                .then(|| (Steering::new(&profile.tilt), profile.tilt.axis)),
+            capabilities: Capabilities::default(),
+        };
+        mapping.capabilities = mapping.collect_capabilities();
+        mapping
+    }
+
+    pub fn bindings(&self) -> &[(Input, Binding)] {
+        &self.bindings
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn collect_capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::default();
+        for (input, binding) in &self.bindings {
+            match &binding.output {
+                Output::None => {}
+                Output::Key(key) => capabilities.add_keys(&[*key]),
+                Output::Combo(keys) => capabilities.add_keys(keys),
+                Output::Rel(rel) => {
+                    if !capabilities.rel.contains(rel) {
+                        capabilities.rel.push(*rel);
+                    }
+                }
+                Output::Abs(code) => {
+                    let code = *code;
+                    let range = match input {
+                        Input::Button(_)
+                        | Input::Region(..)
+                        | Input::Gesture(_)
+                        | Input::Movement(_) => AbsRange {
+                            minimum: binding.value.min(0),
+                            maximum: binding.value.max(0),
+                            rest: 0,
+                            fuzz: 0,
+                            flat: 0,
+                            resolution: 0,
+                        },
+                        Input::Analog(analog) if analog.is_stick() => AbsRange {
+                            flat: self.flats[*analog as usize / 2],
+                            ..self.stick_range
+                        },
+                        Input::Analog(_) | Input::Half(..) => AbsRange {
+                            minimum: 0,
+                            maximum: ANALOG_MAX as i32,
+                            rest: 0,
+                            fuzz: 0,
+                            flat: 0,
+                            resolution: 0,
+                        },
+                    };
+                    match capabilities.abs.iter_mut().find(|(c, _)| *c == code) {
+                        // several inputs on one axis, like the dpad directions
+                        Some((_, known)) => {
+                            known.minimum = known.minimum.min(range.minimum);
+                            known.maximum = known.maximum.max(range.maximum);
+                            if matches!(input, Input::Analog(_) | Input::Half(..)) {
+                                known.rest = range.rest;
+                                known.fuzz = range.fuzz;
+                                known.flat = range.flat;
+                            }
+                        }
+                        None => capabilities.abs.push((code, range)),
+                    }
+                }
+            }
+        }
+        if let Some((x, y, _)) = &self.touch_stick {
+            for code in [*x, *y] {
+                if capabilities.range(code).is_none() {
+                    capabilities.abs.push((code, self.stick_range));
+                }
+            }
+        }
+        match &self.aim {
+            Some((aim, x, y)) if aim.mode() == gyro::Mode::Stick => {
+                for code in [*x, *y] {
+                    if capabilities.range(code).is_none() {
+                        capabilities.abs.push((code, self.stick_range));
+                    }
+                }
+            }
+            Some(_) => {
+                for rel in [EV_REL::REL_X, EV_REL::REL_Y] {
+                    if !capabilities.rel.contains(&rel) {
+                        capabilities.rel.push(rel);
+                    }
+                }
+            }
+            None => {}
+        }
+        if let Some((_, axis)) = &self.steering {
+            if capabilities.range(*axis).is_none() {
+                capabilities.abs.push((*axis, self.stick_range));
+            }
+        }
+        if self.flick.is_some() && !capabilities.rel.contains(&EV_REL::REL_X) {
+            capabilities.rel.push(EV_REL::REL_X);
+        }
+        if self.trackpad.is_some() {
+            capabilities.add_keys(&Trackpad::KEYS);
+            for rel in Trackpad::RELS {
+                if !capabilities.rel.contains(&rel) {
+                    capabilities.rel.push(rel);
+                }
+            }
+        }
+        capabilities
+    }
+
+    /// Values to report for controller `state` at `time`, which only needs to count up from
+    /// any start
+    pub fn frame(&mut self, state: &DS4State, time: Duration) -> Frame {
+        let capabilities = &self.capabilities;
+        let mut frame = Frame {
+            values: capabilities
+                .keys
+                .iter()
+                .map(|key| Value {
+                    code: EventCode::EV_KEY(*key),
+                    value: 0,
+                    rest: 0,
+                })
+                .chain(capabilities.abs.iter().map(|(code, range)| Value {
+                    code: EventCode::EV_ABS(*code),
+                    value: range.rest,
+                    rest: range.rest,
+                }))
+                .chain(capabilities.rel.iter().map(|rel| Value {
+                    code: EventCode::EV_REL(*rel),
+                    value: 0,
+                    rest: 0,
+                }))
+                .collect(),
+            motion: vec![],
+        };
+
+        let gesture = self.recognizer.update(time, &state.touches);
+        let movement = self.detector.update(state);
+        for (input, binding) in &self.bindings {
+            let level = self.level(*input, state, gesture, movement);
+            let keys = match &binding.output {
+                Output::Key(key) => std::slice::from_ref(key),
+                Output::Combo(keys) => keys,
+                _ => &[],
+            };
+            match &binding.output {
+                Output::None => {}
+                Output::Key(_) | Output::Combo(_) => {
+                    let held = match level {
+                        Level::Digital(pressed) => pressed,
+                        Level::Analog { offset, .. } => offset.abs() >= binding.threshold,
+                    };
+                    if held {
+                        for key in keys {
+                            frame.value_mut(EventCode::EV_KEY(*key)).value = 1;
+                        }
+                    }
+                }
+                Output::Abs(code) => {
+                    let code = *code;
+                    let change = match level {
+                        Level::Digital(pressed) => pressed as i32 * binding.value,
+                        Level::Analog { value, rest, .. } => value - rest,
+                    };
+                    frame.value_mut(EventCode::EV_ABS(code)).value += change;
+                }
+                Output::Rel(rel) => match level {
+                    Level::Digital(pressed) => {
+                        frame.value_mut(EventCode::EV_REL(*rel)).value +=
+                            pressed as i32 * binding.value;
+                    }
+                    Level::Analog { offset, .. } => {
+                        let motion = binding.curve.normalized(offset) * binding.speed;
+                        frame.motion.push((*rel, motion));
+                    }
+                },
+            }
+        }
+
+        if let Some(trackpad) = &mut self.trackpad {
+            let pointer = trackpad.update(&state.touches, state.touchpad, time);
+            for key in pointer.buttons {
+                frame.value_mut(EventCode::EV_KEY(key)).value = 1;
+            }
+            frame.motion.extend(pointer.motion);
+        }
+        if let Some((x, y, stick)) = &mut self.touch_stick {
+            let (x, y) = (*x, *y);
+            let (x_offset, y_offset) = stick.update(&state.touches);
+            frame.value_mut(EventCode::EV_ABS(x)).value = self.offset_value(x_offset);
+            frame.value_mut(EventCode::EV_ABS(y)).value = self.offset_value(y_offset);
+        }
+        if let Some((steering, axis)) = &mut self.steering {
+            let axis = *axis;
+            let offset = steering.update(state);
+            frame.value_mut(EventCode::EV_ABS(axis)).value = self.offset_value(offset);
+        }
+        if let Some(flick) = &mut self.flick {
+            frame
+                .motion
+                .push((EV_REL::REL_X, flick.update(state, time)));
+        }
+        if let Some((aim, x, y)) = &mut self.aim {
+            let (x, y) = (*x, *y);
+            let (x_aim, y_aim) = aim.update(state);
+            if aim.mode() == gyro::Mode::Mouse {
+                frame
+                    .motion
+                    .extend([(EV_REL::REL_X, x_aim), (EV_REL::REL_Y, y_aim)]);
+            } else {
+                // the aim adds to where the stick is held
+                let rest = self.stick_range.rest;
+                frame.value_mut(EventCode::EV_ABS(x)).value += self.offset_value(x_aim) - rest;
+                frame.value_mut(EventCode::EV_ABS(y)).value += self.offset_value(y_aim) - rest;
+            }
+        }
+
+        for value in &mut frame.values {
+            if let EventCode::EV_ABS(code) = value.code {
+                let range = self
+                    .capabilities
+                    .range(code)
+                    .expect("every axis has a range");
+                value.value = value.value.clamp(range.minimum, range.maximum);
+            }
+        }
+        frame
+    }
+
+    /// Level of `input` in `state`, in which `gesture` & `movement` completed
+    fn level(
+        &self,
+        input: Input,
+        state: &DS4State,
+        gesture: Option<Gesture>,
+        movement: Option<Movement>,
+    ) -> Level {
+        match input {
+            Input::Button(button) => Level::Digital(state.pressed(button)),
+            Input::Analog(analog) => self.analog(analog, state),
+            Input::Half(analog, half) => {
+                let Level::Analog { offset, .. } = self.analog(analog, state) else {
+                    unreachable!("analog inputs have analog levels")
+                };
+                let offset = match half {
+                    Half::Negative => (-offset).max(0.0),
+                    Half::Positive => offset.max(0.0),
+                };
+                Level::Analog {
+                    value: (offset * ANALOG_MAX as f32).round() as i32,
+                    rest: 0,
+                    offset,
+                }
+            }
+            Input::Region(number, contact) => {
+                let held = contact == Contact::Touch || state.touchpad;
+                let touched = self.regions.get(number as usize - 1).is_some_and(|rect| {
+                    state
+                        .touches
+                        .iter()
+                        .flatten()
+                        .any(|touch| touchpad::contains(rect, touch))
+                });
+                Level::Digital(held && touched)
+            }
+            Input::Gesture(g) => Level::Digital(gesture == Some(g)),
+            Input::Movement(m) => Level::Digital(movement == Some(m)),
+        }
+    }
+
+    fn analog(&self, analog: Analog, state: &DS4State) -> Level {
+        let raw = match analog {
+            Analog::LeftX => state.lsx,
+            Analog::LeftY => state.lsy,
+            Analog::RightX => state.rsx,
+            Analog::RightY => state.rsy,
+            Analog::L2 => state.l2_analog,
+            Analog::R2 => state.r2_analog,
+        };
+        if !analog.is_stick() {
+            return Level::Analog {
+                value: raw as i32,
+                rest: 0,
+                offset: raw as f32 / ANALOG_MAX as f32,
+            };
+        }
+        let value = self.sticks[analog as usize].apply(raw);
+        let middle = ANALOG_MAX as f32 / 2.0;
+        let offset = (value as f32 - middle) / middle;
+        // the center is between two raw values, either of them is at rest
+        let offset = if offset.abs() * middle <= 0.5 {
+            0.0
+        } else {
+            offset
+        };
+        Level::Analog {
+            value: self.scale_stick(value, offset),
+            rest: self.stick_range.rest,
+            offset,
+        }
+    }
+
+    /// Stick value at `offset` from center in the reported range
+    fn offset_value(&self, offset: f32) -> i32 {
+        let middle = ANALOG_MAX as f32 / 2.0;
+        let value = match offset {
+            0.0 => self.stick_range.rest,
+            _ => (middle + offset * middle).round() as i32,
+        };
+        self.scale_stick(value, offset)
+    }
+
+    /// Stick `value` in the range of the controller, at `offset` from center, in the reported range
+    fn scale_stick(&self, value: i32, offset: f32) -> i32 {
+        let range = &self.stick_range;
+        if (range.minimum, range.maximum) == (0, ANALOG_MAX as i32) {
+            return value;
+        }
+        let span = if offset < 0.0 {
+            range.rest - range.minimum
+        } else {
+            range.maximum - range.rest
+        };
+        range.rest + (offset * span as f32).round() as i32
+    }
+}
+
+impl Frame {
+    fn value_mut(&mut self, code: EventCode) -> &mut Value {
+        self.values
+            .iter_mut()
+            .find(|value| value.code == code)
+            .expect("every code of the mapping has a value")
+    }
+}
+
+/// Where reported events go: the virtual device, or a recording of it in tests
+pub trait EventSink {
+    fn write(&mut self, code: EventCode, value: i32) -> io::Result<()>;
+    /// End a report, making the events written since the previous one take effect
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+/// Keeps track of what was reported, to only report what changed
+#[derive(Debug, Clone, Default)]
+pub struct Reporter {
+    last: Vec<Value>,
+    /// Relative motion too small to report yet
+    remainders: Vec<(EV_REL, f32)>,
+}
+
+impl Reporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events to write for `frame`. Codes no longer in it, like those of a profile that was
+    /// switched away from, are returned to rest.
+    pub fn report(&mut self, frame: &Frame) -> Vec<(EventCode, i32)> {
+        let mut events = vec![];
+        for value in &frame.values {
+            let last = self
+                .last
+                .iter()
+                .find(|last| last.code == value.code)
+                .map_or(value.rest, |last| last.value);
+            // relative axes step once per press, and have nothing to release
+            let relative = matches!(value.code, EventCode::EV_REL(_));
+            if last != value.value && !(relative && value.value == 0) {
+                events.push((value.code, value.value));
+            }
+        }
+        for last in &self.last {
+            let gone = !frame.values.iter().any(|value| value.code == last.code);
+            let relative = matches!(last.code, EventCode::EV_REL(_));
+            if gone && !relative && last.value != last.rest {
+                events.push((last.code, last.rest));
+            }
+        }
+        for (rel, motion) in &frame.motion {
+            let remainder = match self.remainders.iter_mut().find(|(r, _)| r == rel) {
+                Some((_, remainder)) => remainder,
+                None => {
+                    self.remainders.push((*rel, 0.0));
+                    &mut self.remainders.last_mut().expect("just pushed").1
+                }
+            };
+            *remainder += motion;
+            let whole = remainder.trunc();
+            *remainder -= whole;
+            if whole != 0.0 {
+                events.push((EventCode::EV_REL(*rel), whole as i32));
+            }
+        }
+        self.last = frame.values.clone();
+        events
+    }
+
+    /// Write the events for `frame` to `sink` as one report
+    pub fn emit(&mut self, frame: &Frame, sink: &mut impl EventSink) -> io::Result<()> {
+        for (code, value) in self.report(frame) {
+            sink.write(code, value)?;
+        }
+        sink.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::Touch;
+
+    fn mapping(map: &str) -> Mapping {
+        let profile: Profile = toml::from_str(&format!("[map]\n{}", map)).unwrap();
+        Mapping::new(&profile, Preset::Generic)
+    }
+
+    fn value(frame: &Frame, code: EventCode) -> i32 {
+        frame.values.iter().find(|v| v.code == code).unwrap().value
+    }
+
+    /// Every report written, in order
+    #[derive(Default)]
+    struct Recorder {
+        pending: Vec<(EventCode, i32)>,
+        reports: Vec<Vec<(EventCode, i32)>>,
+    }
+
+    impl EventSink for Recorder {
+        fn write(&mut self, code: EventCode, value: i32) -> io::Result<()> {
+            self.pending.push((code, value));
+            Ok(())
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.reports.push(std::mem::take(&mut self.pending));
+            Ok(())
+        }
+    }
+
+    /// Reports written for `state` after a controller at rest, with the default profile
+    fn emitted(state: &DS4State) -> Vec<(EventCode, i32)> {
+        let mut mapping = Mapping::new(&Profile::default(), Preset::Generic);
+        let mut reporter = Reporter::new();
+        let mut recorder = Recorder::default();
+        let mut rest = DS4State::initial_state();
+        (rest.lsx, rest.lsy, rest.rsx, rest.rsy) = (127, 127, 127, 127);
+        reporter
+            .emit(&mapping.frame(&rest, Duration::ZERO), &mut recorder)
+            .unwrap();
+        reporter
+            .emit(&mapping.frame(state, Duration::ZERO), &mut recorder)
+            .unwrap();
+        assert_eq!(
+            recorder.reports[0],
+            [],
+            "a controller at rest reports nothing"
+        );
+        recorder.reports.swap_remove(1)
+    }
+
+    #[test]
+    fn inputs_are_named() {
+        for (name, input) in Input::NAMES {
+            assert_eq!(input.to_string(), name);
+        }
+        for button in Button::ALL {
+            let input = Input::Button(button);
+            assert_eq!(input.to_string().parse(), Ok(input));
+        }
+        let region = Input::Region(2, Contact::Click);
+        assert_eq!(region.to_string(), "region2_click");
+        assert_eq!("region2_click".parse(), Ok(region));
+        assert!("left_stick_sideways".parse::<Input>().is_err());
+        assert!("region0_touch".parse::<Input>().is_err());
+        assert!("region1_tap".parse::<Input>().is_err());
+        for (name, gesture) in Gesture::NAMES {
+            assert_eq!(name.parse(), Ok(Input::Gesture(gesture)));
+        }
+        for (name, movement) in Movement::NAMES {
+            let input = Input::Movement(movement);
+            assert_eq!(name.parse(), Ok(input));
+            assert_eq!(input.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn default_mapping_matches_profile_tables() {
+        let mut mapping = Mapping::new(&Profile::default(), Preset::Generic);
+        let mut state = DS4State::initial_state();
+        state.cross = true;
+        state.up = true;
+        state.lsx = 255;
+        state.rsy = 128;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_KEY(EV_KEY::BTN_SOUTH)), 1);
+        assert_eq!(value(&frame, EventCode::EV_KEY(EV_KEY::BTN_EAST)), 0);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_HAT0Y)), -1);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_TILT_X)), 255);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_RY)), 128);
+
+        let capabilities = mapping.capabilities();
+        assert_eq!(capabilities.keys.len(), 14);
+        let hat = capabilities.range(EV_ABS::ABS_HAT0X).unwrap();
+        assert_eq!((hat.minimum, hat.maximum, hat.rest), (-1, 1, 0));
+        assert_eq!(capabilities.range(EV_ABS::ABS_TILT_X).unwrap().flat, 15);
+    }
+
+    #[test]
+    fn every_button_is_emitted() {
+        for button in Button::ALL {
+            let mut state = DS4State::initial_state();
+            (state.lsx, state.lsy, state.rsx, state.rsy) = (127, 127, 127, 127);
+            state.set_pressed(button, true);
+            let events = emitted(&state);
+            assert_eq!(events.len(), 1, "{:?} reports {:?}", button, events);
+        }
+    }
+
+    #[test]
+    fn every_stick_axis_is_emitted() {
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsx, state.rsy) = (0, 255, 255, 0);
+        let events = emitted(&state);
+        for code in [
+            EV_ABS::ABS_TILT_X,
+            EV_ABS::ABS_TILT_Y,
+            EV_ABS::ABS_RX,
+            EV_ABS::ABS_RY,
+        ] {
+            assert!(events.iter().any(|(c, _)| *c == EventCode::EV_ABS(code)));
+        }
+    }
+
+    #[test]
+    fn map_replaces_and_adds_bindings() {
+        let mut mapping = mapping(
+            "cross = \"none\"\nleft_stick_up = \"KEY_W\"\nr2_analog = \"ABS_GAS\"\nl3 = { code = \"REL_WHEEL\", value = -1 }\n",
+        );
+        let capabilities = mapping.capabilities();
+        assert!(!capabilities.keys.contains(&EV_KEY::BTN_SOUTH));
+        assert!(capabilities.keys.contains(&EV_KEY::KEY_W));
+        assert_eq!(capabilities.rel, [EV_REL::REL_WHEEL]);
+
+        let mut state = DS4State::initial_state();
+        state.lsy = 20;
+        state.r2_analog = 200;
+        state.l3 = true;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_KEY(EV_KEY::KEY_W)), 1);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_GAS)), 200);
+        assert_eq!(value(&frame, EventCode::EV_REL(EV_REL::REL_WHEEL)), -1);
+
+        state.lsy = 100;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_KEY(EV_KEY::KEY_W)), 0);
+    }
+
+    #[test]
+    fn combos_press_every_key() {
+        let mut mapping = mapping("select = \"KEY_LEFTCTRL+KEY_LEFTSHIFT+KEY_TAB\"");
+        let combo = Output::Combo(vec![
+            EV_KEY::KEY_LEFTCTRL,
+            EV_KEY::KEY_LEFTSHIFT,
+            EV_KEY::KEY_TAB,
+        ]);
+        assert_eq!(combo.to_string().parse(), Ok(combo));
+        assert!("KEY_LEFTCTRL+ABS_X".parse::<Output>().is_err());
+
+        let mut state = DS4State::initial_state();
+        state.select = true;
+        let mut reporter = Reporter::new();
+        let pressed: Vec<_> = reporter
+            .report(&mapping.frame(&state, Duration::ZERO))
+            .into_iter()
+            .filter(|(code, _)| matches!(code, EventCode::EV_KEY(_)))
+            .collect();
+        assert_eq!(
+            pressed,
+            [
+                (EventCode::EV_KEY(EV_KEY::KEY_LEFTCTRL), 1),
+                (EventCode::EV_KEY(EV_KEY::KEY_LEFTSHIFT), 1),
+                (EventCode::EV_KEY(EV_KEY::KEY_TAB), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyboard_and_mouse_codes_are_split_off() {
+        let (gamepad, desktop) = mapping(
+            "touchpad = \"KEY_F1\"\nps = \"KEY_LEFTMETA\"\nr2 = \"BTN_LEFT\"\nright_x = \"REL_X\"",
+        )
+        .capabilities()
+        .clone()
+        .split_desktop();
+        assert_eq!(
+            desktop.keys,
+            [EV_KEY::BTN_LEFT, EV_KEY::KEY_F1, EV_KEY::KEY_LEFTMETA]
+        );
+        assert_eq!(desktop.rel, [EV_REL::REL_X]);
+        assert!(gamepad.keys.contains(&EV_KEY::BTN_SOUTH));
+        assert!(!gamepad.keys.contains(&EV_KEY::KEY_F1));
+        assert!(gamepad.rel.is_empty());
+    }
+
+    #[test]
+    fn changed_flat_is_a_changed_range() {
+        let profile: Profile = toml::from_str("[right_stick]\nflat = 40").unwrap();
+        let before = Mapping::new(&Profile::default(), Preset::Generic)
+            .capabilities()
+            .clone();
+        let after = Mapping::new(&profile, Preset::Generic)
+            .capabilities()
+            .clone();
+        assert_eq!(
+            before.changed_ranges(&after),
+            [EV_ABS::ABS_RX, EV_ABS::ABS_RY]
+        );
+        assert!(before.changed_ranges(&before).is_empty());
+    }
+
+    #[test]
+    fn trackpad_is_a_mouse() {
+        let profile: Profile = toml::from_str("[touchpad]\nmode = \"trackpad\"").unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Generic);
+        let (gamepad, desktop) = mapping.capabilities().clone().split_desktop();
+        assert!(!gamepad.keys.contains(&EV_KEY::BTN_TOUCH));
+        assert_eq!(desktop.keys, Trackpad::KEYS);
+        assert_eq!(desktop.rel, Trackpad::RELS);
+
+        let mut state = DS4State::initial_state();
+        state.touchpad = true;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_KEY(EV_KEY::BTN_LEFT)), 1);
+
+        state.touchpad = false;
+        state.touches[0] = Some(Touch {
+            id: 1,
+            x: 900,
+            y: 400,
+        });
+        mapping.frame(&state, Duration::from_millis(10));
+        state.touches[0] = Some(Touch {
+            id: 1,
+            x: 920,
+            y: 400,
+        });
+        let frame = mapping.frame(&state, Duration::from_millis(14));
+        let (rel, motion) = frame.motion[0];
+        assert_eq!(rel, EV_REL::REL_X);
+        assert!((motion - 10.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn touchpad_regions_are_buttons() {
+        let profile: Profile = toml::from_str(
+            "[touchpad]\nregions = \"halves\"\n[map]\ntouchpad = \"none\"\nregion1_click = \"BTN_SELECT\"\nregion2_click = \"BTN_START\"\nregion2_touch = \"KEY_T\"\n",
+        )
+        .unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Generic);
+        let select = EventCode::EV_KEY(EV_KEY::BTN_SELECT);
+        let start = EventCode::EV_KEY(EV_KEY::BTN_START);
+        let touch = EventCode::EV_KEY(EV_KEY::KEY_T);
+
+        let mut state = DS4State::initial_state();
+        state.touches[0] = Some(Touch {
+            id: 1,
+            x: 1500,
+            y: 400,
+        });
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, touch), 1);
+        assert_eq!(value(&frame, start), 0);
+
+        state.touchpad = true;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!((value(&frame, select), value(&frame, start)), (0, 1));
+        assert!(!mapping.capabilities().keys.contains(&EV_KEY::BTN_TOUCH));
+    }
+
+    #[test]
+    fn touchpad_stick_is_a_third_stick() {
+        let profile: Profile = toml::from_str(
+            "[touchpad]\nmode = \"stick\"\n[touchpad.stick]\norigin = \"center\"\ndeadzone = 0.0\n",
+        )
+        .unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Xbox360);
+        let rudder = mapping.capabilities().range(EV_ABS::ABS_RUDDER).copied();
+        assert_eq!(rudder, Some(Preset::Xbox360.stick_range()));
+
+        let mut state = DS4State::initial_state();
+        state.touches[0] = Some(Touch {
+            id: 1,
+            x: 1920,
+            y: 471,
+        });
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_RUDDER)), 32767);
+        // half a unit above the center of the pad
+        assert!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_THROTTLE)).abs() < 100);
+        state.touches[0] = None;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_RUDDER)), 0);
+    }
+
+    #[test]
+    fn gyro_deflects_the_right_stick() {
+        let profile: Profile = toml::from_str("[gyro]\nmode = \"stick\"\n").unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Xbox360);
+        let mut state = DS4State::initial_state();
+        (state.rsx, state.rsy) = (127, 127);
+        // turning right at half the speed of full deflection
+        state.motion.gyro = [0, -90 * crate::motion::GYRO_RES_PER_DEG_S, 0];
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert!((value(&frame, EventCode::EV_ABS(EV_ABS::ABS_RX)) - 16384).abs() <= 1);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_RY)), 0);
+
+        let profile: Profile = toml::from_str("[gyro]\nmode = \"mouse\"\n").unwrap();
+        let mapping = Mapping::new(&profile, Preset::Generic);
+        let (_, desktop) = mapping.capabilities().clone().split_desktop();
+        assert_eq!(desktop.rel, [EV_REL::REL_X, EV_REL::REL_Y]);
+    }
+
+    #[test]
+    fn flick_stick_turns_the_mouse_instead_of_the_right_stick() {
+        let profile: Profile =
+            toml::from_str("[flick_stick]\nenabled = true\nflick_time_ms = 0\n").unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Generic);
+        let capabilities = mapping.capabilities();
+        assert!(capabilities.range(EV_ABS::ABS_RX).is_none());
+        assert!(capabilities.rel.contains(&EV_REL::REL_X));
+
+        let mut state = DS4State::initial_state();
+        (state.rsx, state.rsy) = (0, 128);
+        let frame = mapping.frame(&state, Duration::ZERO);
+        // a quarter turn to the left
+        let (rel, motion) = frame.motion[0];
+        assert_eq!(rel, EV_REL::REL_X);
+        assert!((motion + 900.0).abs() < 5.0, "{}", motion);
+    }
+
+    #[test]
+    fn tilt_steers_its_axis() {
+        let profile: Profile = toml::from_str("[tilt]\nenabled = true\n").unwrap();
+        let mut mapping = Mapping::new(&profile, Preset::Xbox360);
+        let wheel = mapping.capabilities().range(EV_ABS::ABS_WHEEL).copied();
+        assert_eq!(wheel, Some(Preset::Xbox360.stick_range()));
+
+        // lying flat is center
+        let mut state = DS4State::initial_state();
+        state.motion.accel = [0, crate::motion::ACCEL_RES_PER_G, 0];
+        let frame = mapping.frame(&state, Duration::ZERO);
+        assert_eq!(value(&frame, EventCode::EV_ABS(EV_ABS::ABS_WHEEL)), 0);
+    }
+
+    #[test]
+    fn gestures_press_for_one_report() {
+        let mut mapping =
+            mapping("swipe_right = \"KEY_NEXTSONG\"\npinch = { code = \"REL_WHEEL\", value = -1 }");
+        let next = EventCode::EV_KEY(EV_KEY::KEY_NEXTSONG);
+        let mut reporter = Reporter::new();
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsx, state.rsy) = (127, 127, 127, 127);
+        let mut report = |touch: Option<Touch>, ms| {
+            state.touches[0] = touch;
+            reporter.report(&mapping.frame(&state, Duration::from_millis(ms)))
+        };
+        assert_eq!(
+            report(
+                Some(Touch {
+                    id: 1,
+                    x: 400,
+                    y: 400
+                }),
+                0
+            ),
+            []
+        );
+        assert_eq!(
+            report(
+                Some(Touch {
+                    id: 1,
+                    x: 1000,
+                    y: 420
+                }),
+                100
+            ),
+            []
+        );
+        assert_eq!(report(None, 104), [(next, 1)]);
+        assert_eq!(report(None, 108), [(next, 0)]);
+    }
+
+    #[test]
+    fn flipping_presses_for_one_report() {
+        let mut mapping = mapping("flip = \"KEY_MUTE\"");
+        let mute = EventCode::EV_KEY(EV_KEY::KEY_MUTE);
+        let mut reporter = Reporter::new();
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsx, state.rsy) = (127, 127, 127, 127);
+        let g = crate::motion::ACCEL_RES_PER_G;
+        let mut report = |y, sensor_time| {
+            state.motion.accel = [0, y, 0];
+            state.sensor_time = sensor_time;
+            reporter.report(&mapping.frame(&state, Duration::ZERO))
+        };
+        assert_eq!(report(g, 0), []);
+        assert_eq!(report(-g, 750), [(mute, 1)]);
+        assert_eq!(report(-g, 1500), [(mute, 0)]);
+    }
+
+    #[test]
+    fn stick_motion_accumulates_fractions() {
+        let mut mapping = mapping("right_x = { code = \"REL_X\", speed = 0.25 }");
+        let mut reporter = Reporter::new();
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsy) = (127, 127, 127);
+        state.rsx = 255;
+        let moved: Vec<_> = (0..8)
+            .flat_map(|_| reporter.report(&mapping.frame(&state, Duration::ZERO)))
+            .collect();
+        assert_eq!(
+            moved,
+            [
+                (EventCode::EV_REL(EV_REL::REL_X), 1),
+                (EventCode::EV_REL(EV_REL::REL_X), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_step_relative_axes_once_per_press() {
+        let mut mapping = mapping("l3 = { code = \"REL_WHEEL\", value = -1 }");
+        let mut reporter = Reporter::new();
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsx, state.rsy) = (127, 127, 127, 127);
+        state.l3 = true;
+        let wheel = EventCode::EV_REL(EV_REL::REL_WHEEL);
+        assert_eq!(
+            reporter.report(&mapping.frame(&state, Duration::ZERO)),
+            [(wheel, -1)]
+        );
+        assert_eq!(reporter.report(&mapping.frame(&state, Duration::ZERO)), []);
+        state.l3 = false;
+        assert_eq!(reporter.report(&mapping.frame(&state, Duration::ZERO)), []);
+    }
+
+    #[test]
+    fn trigger_threshold_presses_key() {
+        let mut mapping = mapping("r2_analog = { code = \"KEY_SPACE\", threshold = 0.9 }");
+        let mut state = DS4State::initial_state();
+        state.r2_analog = 200;
+        let space = EventCode::EV_KEY(EV_KEY::KEY_SPACE);
+        assert_eq!(value(&mapping.frame(&state, Duration::ZERO), space), 0);
+        state.r2_analog = 240;
+        assert_eq!(value(&mapping.frame(&state, Duration::ZERO), space), 1);
+    }
+
+    #[test]
+    fn bindings_roundtrip_through_toml() {
+        let profile: Profile = toml::from_str(
+            "[map]\ncross = \"KEY_A\"\nup = { code = \"ABS_HAT0Y\", value = -1, threshold = 0.5, speed = 10.0 }\n",
+        )
+        .unwrap();
+        let serialized = toml::to_string(&profile).unwrap();
+        assert!(serialized.contains("cross = \"KEY_A\""));
+        assert_eq!(toml::from_str::<Profile>(&serialized).unwrap(), profile);
+    }
+
+    #[test]
+    fn reporter_only_reports_changes() {
+        let mut default = Mapping::new(&Profile::default(), Preset::Generic);
+        let mut reporter = Reporter::new();
+        let mut state = DS4State::initial_state();
+        state.lsx = 127;
+        state.lsy = 127;
+        state.rsx = 127;
+        state.rsy = 127;
+        assert_eq!(reporter.report(&default.frame(&state, Duration::ZERO)), []);
+
+        state.cross = true;
+        let cross = EventCode::EV_KEY(EV_KEY::BTN_SOUTH);
+        assert_eq!(
+            reporter.report(&default.frame(&state, Duration::ZERO)),
+            [(cross, 1)]
+        );
+        assert_eq!(reporter.report(&default.frame(&state, Duration::ZERO)), []);
+
+        // switching to a mapping without the key releases it
+        let mut other = mapping("cross = \"KEY_A\"");
+        let events = reporter.report(&other.frame(&state, Duration::ZERO));
+        assert_eq!(events, [(EventCode::EV_KEY(EV_KEY::KEY_A), 1), (cross, 0)]);
+    }
+}
@@ -0,0 +1,372 @@
+use std::time::Duration;
+
+use evdev_rs::enums::{EventCode, InputProp, EV_ABS, EV_MSC};
+
+use crate::{
+    hid::DS4State,
+    mapping::{AbsRange, Capabilities},
+};
+
+/// Accelerometer units per g, as the kernel's hid-sony driver reports them
+pub const ACCEL_RES_PER_G: i32 = 8192;
+/// Gyroscope units per degree per second, as the kernel's hid-sony driver reports them
+pub const GYRO_RES_PER_DEG_S: i32 = 1024;
+/// Id of the USB feature report holding the controller's motion sensor calibration
+pub const CALIBRATION_REPORT: u8 = 0x02;
+/// Length of the calibration feature report, its id included
+pub const CALIBRATION_REPORT_LEN: usize = 37;
+
+/// Time between sensor times `from` & `to` of two reports, which wrap around
+pub fn elapsed(from: u16, to: u16) -> Duration {
+    Duration::from_nanos(to.wrapping_sub(from) as u64 * 16_000 / 3)
+}
+
+/// Time of consecutive reports, by their sensor time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SensorClock {
+    /// Sensor time of the last report
+    last: Option<u16>,
+    /// Time from the first report to the last
+    now: Duration,
+}
+
+impl SensorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time since the last report until `state`, none for the first
+    pub fn tick(&mut self, state: &DS4State) -> Duration {
+        let time = self
+            .last
+            .map_or(Duration::ZERO, |last| elapsed(last, state.sensor_time));
+        self.last = Some(state.sensor_time);
+        self.now += time;
+        time
+    }
+
+    /// Time from the first report to the last
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+}
+
+const ACCEL_RANGE: i32 = 4 * ACCEL_RES_PER_G;
+const GYRO_RANGE: i32 = 2048 * GYRO_RES_PER_DEG_S;
+
+const GYRO_CODES: [EV_ABS; 3] = [EV_ABS::ABS_RX, EV_ABS::ABS_RY, EV_ABS::ABS_RZ];
+const ACCEL_CODES: [EV_ABS; 3] = [EV_ABS::ABS_X, EV_ABS::ABS_Y, EV_ABS::ABS_Z];
+
+/// Codes of a motion sensor device reporting acceleration on ABS_X/Y/Z and rotation rate on
+/// ABS_RX/RY/RZ, as the kernel's hid-sony driver does
+pub fn capabilities() -> Capabilities {
+    let axis = |range: i32, resolution: i32| AbsRange {
+        minimum: -range,
+        maximum: range,
+        rest: 0,
+        fuzz: 0,
+        flat: 0,
+        resolution,
+    };
+    let accel = ACCEL_CODES.map(|code| (code, axis(ACCEL_RANGE, ACCEL_RES_PER_G)));
+    let gyro = GYRO_CODES.map(|code| (code, axis(GYRO_RANGE, GYRO_RES_PER_DEG_S)));
+    Capabilities {
+        keys: vec![],
+        abs: accel.into_iter().chain(gyro).collect(),
+        rel: vec![],
+        msc: vec![EV_MSC::MSC_TIMESTAMP],
+        props: vec![InputProp::INPUT_PROP_ACCELEROMETER],
+    }
+}
+
+/// Scaling of a raw sensor axis: `(raw - bias) * numerator / denominator`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisCalibration {
+    pub bias: i32,
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl AxisCalibration {
+    pub fn apply(&self, raw: i16) -> i32 {
+        let scaled = (raw as i64 - self.bias as i64) * self.numerator as i64;
+        (scaled / self.denominator as i64) as i32
+    }
+}
+
+/// Scaling of the controller's motion sensors to the units of [`ACCEL_RES_PER_G`] and
+/// [`GYRO_RES_PER_DEG_S`]. The default is the nominal scale of the sensors, for controllers
+/// whose calibration can't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    /// Pitch, yaw & roll
+    pub gyro: [AxisCalibration; 3],
+    /// X, y & z
+    pub accel: [AxisCalibration; 3],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        // the raw readings have 16 gyro units per degree per second, and the accelerometer's
+        // own units per g
+        let gyro = AxisCalibration {
+            bias: 0,
+            numerator: GYRO_RES_PER_DEG_S,
+            denominator: 16,
+        };
+        let accel = AxisCalibration {
+            bias: 0,
+            numerator: 1,
+            denominator: 1,
+        };
+        Self {
+            gyro: [gyro; 3],
+            accel: [accel; 3],
+        }
+    }
+}
+
+impl Calibration {
+    /// Calibration in feature report `buf`, read over USB, `None` when it's too short. Axes
+    /// with a calibration that can't be used keep the default.
+    pub fn from_report(buf: &[u8]) -> Option<Self> {
+        if buf.len() < CALIBRATION_REPORT_LEN {
+            return None;
+        }
+        let value = |i: usize| i16::from_le_bytes([buf[i], buf[i + 1]]) as i32;
+        let biases = [value(1), value(3), value(5)];
+        // over USB, each axis has its plus & minus reference next to each other
+        let references = [
+            (value(7), value(9)),
+            (value(11), value(13)),
+            (value(15), value(17)),
+        ];
+        let speed_2x = value(19) + value(21);
+        let default = Self::default();
+
+        let mut gyro = default.gyro;
+        for (axis, (bias, (plus, minus))) in gyro.iter_mut().zip(biases.into_iter().zip(references))
+        {
+            if plus != minus {
+                *axis = AxisCalibration {
+                    bias,
+                    numerator: speed_2x * GYRO_RES_PER_DEG_S,
+                    denominator: plus - minus,
+                };
+            }
+        }
+        let mut accel = default.accel;
+        for (i, axis) in accel.iter_mut().enumerate() {
+            let (plus, minus) = (value(23 + 4 * i), value(25 + 4 * i));
+            // the references are at +1 g & -1 g
+            let range_2g = plus - minus;
+            if range_2g != 0 {
+                *axis = AxisCalibration {
+                    bias: plus - range_2g / 2,
+                    numerator: 2 * ACCEL_RES_PER_G,
+                    denominator: range_2g,
+                };
+            }
+        }
+        Some(Self { gyro, accel })
+    }
+
+    /// Fill in the calibrated motion sensor readings of `state`
+    pub fn apply(&self, state: &mut DS4State) {
+        let scale =
+            |axes: &[AxisCalibration; 3], raw: &[i16; 3]| [0, 1, 2].map(|i| axes[i].apply(raw[i]));
+        state.motion = Motion {
+            gyro: scale(&self.gyro, &state.gyro),
+            accel: scale(&self.accel, &state.accel),
+        };
+    }
+}
+
+/// Motion sensor readings, in the units of [`GYRO_RES_PER_DEG_S`] and [`ACCEL_RES_PER_G`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Motion {
+    /// Rotation rate around the pitch, yaw & roll axes
+    pub gyro: [i32; 3],
+    /// Acceleration along the x, y & z axes
+    pub accel: [i32; 3],
+}
+
+impl Motion {
+    /// Rotation rate around the pitch, yaw & roll axes in degrees per second
+    pub fn rotation(&self) -> [f32; 3] {
+        self.gyro
+            .map(|rate| rate as f32 / GYRO_RES_PER_DEG_S as f32)
+    }
+
+    /// Acceleration along the x, y & z axes in g
+    pub fn acceleration(&self) -> [f32; 3] {
+        self.accel
+            .map(|accel| accel as f32 / ACCEL_RES_PER_G as f32)
+    }
+}
+
+/// Turns the motion sensor readings of each controller report into events of the motion
+/// sensor device
+#[derive(Debug, Clone, Default)]
+pub struct Sensors {
+    /// Sensor time of the last report
+    last: Option<u16>,
+    /// Microseconds since the sensor time started counting, as the kernel reports them
+    timestamp: u32,
+}
+
+impl Sensors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events for the calibrated readings of `state`, the timestamp first. Unchanged axes are left to the
+    /// input subsystem to drop.
+    pub fn report(&mut self, state: &DS4State) -> Vec<(EventCode, i32)> {
+        // the sensor time wraps around, so only the time between reports adds up
+        let elapsed = match self.last {
+            Some(last) => state.sensor_time.wrapping_sub(last),
+            None => state.sensor_time,
+        };
+        self.timestamp = self.timestamp.wrapping_add(elapsed as u32 * 16 / 3);
+        self.last = Some(state.sensor_time);
+
+        let motion = &state.motion;
+        let mut events = vec![(
+            EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP),
+            self.timestamp as i32,
+        )];
+        for (code, value) in GYRO_CODES
+            .into_iter()
+            .zip(motion.gyro)
+            .chain(ACCEL_CODES.into_iter().zip(motion.accel))
+        {
+            events.push((EventCode::EV_ABS(code), value));
+        }
+        events
+    }
+}
+
+/// Fixtures for the tests of the modules using the motion sensors
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// Sensor time `ms` in, which counts 3 units every 16 µs
+    pub fn sensor_time(ms: u32) -> u16 {
+        (ms * 3000 / 16) as u16
+    }
+
+    /// State of report number `report`, as reports come every 4 ms
+    pub fn report(report: u32) -> DS4State {
+        DS4State {
+            sensor_time: sensor_time(report * 4),
+            ..DS4State::default()
+        }
+    }
+
+    pub fn assert_near(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| (a - e).abs() <= tolerance);
+        assert!(close, "expected {:?}, but was {:?}", expected, actual);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calibration report with the values at byte indexes 1, 3, 5, ...
+    fn report(values: [i16; 17]) -> Vec<u8> {
+        let mut buf = vec![CALIBRATION_REPORT];
+        for value in values {
+            buf.extend(value.to_le_bytes());
+        }
+        // the last two bytes are unused
+        buf.extend([0, 0]);
+        buf
+    }
+
+    #[test]
+    fn calibration_scales_to_kernel_units() {
+        let calibration = Calibration::from_report(&report([
+            // gyro biases, then plus & minus references of pitch, yaw & roll
+            3, -2, 0, 8000, -8000, 8100, -8100, 7900, -7900,
+            // speed of the references, then accelerometer plus & minus references
+            500, 500, 8300, -8100, 8192, -8192, 8000, -8400,
+        ]))
+        .unwrap();
+
+        // 16000 units between the gyro references are 1000 degrees per second
+        assert_eq!(calibration.gyro[0].apply(16003), 1000 * GYRO_RES_PER_DEG_S);
+        assert_eq!(calibration.gyro[1].apply(-2), 0);
+        // the plus reference is 1 g, the bias halfway to the minus reference
+        assert_eq!(calibration.accel[0].apply(8300), ACCEL_RES_PER_G);
+        assert_eq!(calibration.accel[0].apply(100), 0);
+        assert_eq!(calibration.accel[1].apply(-8192), -ACCEL_RES_PER_G);
+        assert_eq!(calibration.accel[2].apply(-200), 0);
+    }
+
+    #[test]
+    fn clock_adds_up_wrapping_sensor_time() {
+        let mut clock = SensorClock::new();
+        assert_eq!(clock.tick(&testing::report(0)), Duration::ZERO);
+        for report in 1..=100 {
+            assert_eq!(
+                clock.tick(&testing::report(report)),
+                Duration::from_millis(4)
+            );
+        }
+        // the sensor time wrapped around after 350 ms
+        assert_eq!(clock.now(), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn unusable_calibration_keeps_the_default() {
+        let calibration = Calibration::from_report(&report([0; 17])).unwrap();
+        assert_eq!(calibration, Calibration::default());
+        assert_eq!(Calibration::from_report(&[CALIBRATION_REPORT; 20]), None);
+    }
+
+    #[test]
+    fn timestamp_counts_microseconds_across_wraps() {
+        let mut sensors = Sensors::new();
+        let mut state = DS4State {
+            sensor_time: 300,
+            ..DS4State::default()
+        };
+        let timestamp = |events: Vec<(EventCode, i32)>| events[0];
+        assert_eq!(
+            timestamp(sensors.report(&state)),
+            (EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP), 1600)
+        );
+        state.sensor_time = 3;
+        // 65239 units later, wrapping around
+        assert_eq!(timestamp(sensors.report(&state)).1, 1600 + 347941);
+    }
+
+    #[test]
+    fn readings_are_reported_on_every_axis() {
+        let mut sensors = Sensors::new();
+        let mut state = DS4State {
+            gyro: [16, -32, 0],
+            accel: [0, 8192, -100],
+            ..DS4State::default()
+        };
+        Calibration::default().apply(&mut state);
+        let events = sensors.report(&state);
+        assert_eq!(
+            events[1..],
+            [
+                (EventCode::EV_ABS(EV_ABS::ABS_RX), 1024),
+                (EventCode::EV_ABS(EV_ABS::ABS_RY), -2048),
+                (EventCode::EV_ABS(EV_ABS::ABS_RZ), 0),
+                (EventCode::EV_ABS(EV_ABS::ABS_X), 0),
+                (EventCode::EV_ABS(EV_ABS::ABS_Y), 8192),
+                (EventCode::EV_ABS(EV_ABS::ABS_Z), -100),
+            ]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::VecDeque, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{hid::DS4State, motion::SensorClock};
+
+/// Difference of the acceleration from 1 g below which the controller is held calmly
+const CALM: f32 = 0.25;
+/// Acceleration along the controller's y axis in g beyond which it lies face up or face down
+const FLAT: f32 = 0.7;
+
+/// Motion gesture, which is bindable like a button that's pressed for one report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Movement {
+    /// Shaking the controller back and forth
+    Shake,
+    /// Knocking on the controller once
+    Tap,
+    /// Turning the controller upside down
+    Flip,
+}
+
+impl Movement {
+    /// Names of the movements in config files
+    pub const NAMES: [(&'static str, Movement); 3] = [
+        ("shake", Movement::Shake),
+        ("tap", Movement::Tap),
+        ("flip", Movement::Flip),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let (name, _) = Movement::NAMES
+            .iter()
+            .find(|(_, m)| m == self)
+            .expect("every movement is named");
+        name
+    }
+}
+
+/// Motion gesture settings of a profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Movements {
+    /// Average difference of the acceleration from 1 g over `shake_time_ms` which is a shake
+    pub shake_threshold: f32,
+    pub shake_time_ms: u64,
+    /// Difference of the acceleration from 1 g which is a tap
+    pub tap_threshold: f32,
+    /// Longest a tap lasts, the controller being calm before and after
+    pub tap_time_ms: u64,
+    /// Longest turning the controller from face up to face down takes for a flip
+    pub flip_time_ms: u64,
+}
+
+impl Default for Movements {
+    fn default() -> Self {
+        Self {
+            shake_threshold: 1.0,
+            shake_time_ms: 300,
+            tap_threshold: 1.0,
+            tap_time_ms: 50,
+            flip_time_ms: 1000,
+        }
+    }
+}
+
+/// Acceleration of the controller at a time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub time: Duration,
+    /// Acceleration along the controller's axes, in g
+    pub acceleration: [f32; 3],
+}
+
+impl Sample {
+    /// Difference of the acceleration from gravity alone, in g
+    fn jolt(&self) -> f32 {
+        let [x, y, z] = self.acceleration;
+        ((x * x + y * y + z * z).sqrt() - 1.0).abs()
+    }
+}
+
+/// Movement the newest of `samples` completes, which are in order of time. A shake is
+/// recognized before a tap, and a tap before a flip.
+pub fn detect(samples: &[Sample], settings: &Movements) -> Option<Movement> {
+    let newest = samples.last()?;
+    let since = |ms| newest.time.saturating_sub(Duration::from_millis(ms));
+
+    // shaking lasts the whole shake time, a single jolt doesn't
+    let shake_time = Duration::from_millis(settings.shake_time_ms);
+    if newest.time - samples[0].time >= shake_time {
+        let start = newest.time - shake_time;
+        let shaking: Vec<f32> = samples
+            .iter()
+            .filter(|s| s.time >= start)
+            .map(Sample::jolt)
+            .collect();
+        let energy = shaking.iter().map(|j| j * j).sum::<f32>() / shaking.len() as f32;
+        if energy.sqrt() >= settings.shake_threshold {
+            return Some(Movement::Shake);
+        }
+    }
+
+    let start = since(settings.tap_time_ms);
+    let first = samples.iter().position(|s| s.time >= start)?;
+    let calm_before = first > 0 && samples[first - 1].jolt() < CALM;
+    let spiked = samples[first..]
+        .iter()
+        .any(|s| s.jolt() >= settings.tap_threshold);
+    if calm_before && spiked && newest.jolt() < CALM {
+        return Some(Movement::Tap);
+    }
+
+    let start = since(settings.flip_time_ms);
+    let face_up = samples
+        .iter()
+        .any(|s| s.time >= start && s.acceleration[1] >= FLAT);
+    if face_up && newest.acceleration[1] <= -FLAT && newest.jolt() < CALM {
+        return Some(Movement::Flip);
+    }
+    None
+}
+
+/// Detects movements in the motion of consecutive reports, each one once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detector {
+    settings: Movements,
+    clock: SensorClock,
+    samples: VecDeque<Sample>,
+}
+
+impl Detector {
+    pub fn new(settings: &Movements) -> Self {
+        Self {
+            settings: settings.clone(),
+            clock: SensorClock::new(),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Movement completed by the motion of `state`, if any
+    pub fn update(&mut self, state: &DS4State) -> Option<Movement> {
+        self.clock.tick(state);
+        let time = self.clock.now();
+        self.samples.push_back(Sample {
+            time,
+            acceleration: state.motion.acceleration(),
+        });
+
+        // every detection looks back at most this far, and at the sample just before
+        let settings = &self.settings;
+        let span = [
+            settings.shake_time_ms,
+            settings.tap_time_ms,
+            settings.flip_time_ms,
+        ]
+        .into_iter()
+        .max()
+        .map_or(Duration::ZERO, Duration::from_millis);
+        while self.samples.len() > 1 && self.samples[1].time + span <= time {
+            self.samples.pop_front();
+        }
+
+        let movement = detect(self.samples.make_contiguous(), settings);
+        // a movement starts over, so it isn't detected again in the same samples
+        if movement.is_some() {
+            self.samples.clear();
+        }
+        movement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{testing, ACCEL_RES_PER_G};
+
+    /// Samples every 4 ms, accelerating by each of `accelerations` in turn
+    fn samples(accelerations: &[[f32; 3]]) -> Vec<Sample> {
+        accelerations
+            .iter()
+            .enumerate()
+            .map(|(i, acceleration)| Sample {
+                time: Duration::from_millis(i as u64 * 4),
+                acceleration: *acceleration,
+            })
+            .collect()
+    }
+
+    const FACE_UP: [f32; 3] = [0.0, 1.0, 0.0];
+    const FACE_DOWN: [f32; 3] = [0.0, -1.0, 0.0];
+
+    #[test]
+    fn shaking_back_and_forth_is_a_shake() {
+        let shaking: Vec<[f32; 3]> = (0..100)
+            .map(|i| [if i % 10 < 5 { 2.5 } else { -2.5 }, 1.0, 0.0])
+            .collect();
+        let settings = Movements::default();
+        assert_eq!(detect(&samples(&shaking), &settings), Some(Movement::Shake));
+        // shaking shorter than the shake time isn't
+        assert_eq!(detect(&samples(&shaking[..50]), &settings), None);
+    }
+
+    #[test]
+    fn short_spike_is_a_tap() {
+        let mut tapped = vec![FACE_UP; 20];
+        tapped[15] = [0.0, 3.0, 0.0];
+        tapped[16] = [0.0, 0.5, 0.0];
+        let settings = Movements::default();
+        assert_eq!(detect(&samples(&tapped), &settings), Some(Movement::Tap));
+
+        // a spike still going on, or a long one, isn't
+        assert_eq!(detect(&samples(&tapped[..17]), &settings), None);
+        let mut pushed = vec![FACE_UP; 20];
+        pushed[5..17].fill([0.0, 3.0, 0.0]);
+        assert_eq!(detect(&samples(&pushed), &settings), None);
+    }
+
+    #[test]
+    fn turning_upside_down_is_a_flip() {
+        let turning: Vec<[f32; 3]> = (0..=60)
+            .map(|i| {
+                let (sin, cos) = (i as f32 * 3.0).to_radians().sin_cos();
+                [0.0, cos, sin]
+            })
+            .collect();
+        let settings = Movements::default();
+        assert_eq!(detect(&samples(&turning), &settings), Some(Movement::Flip));
+        assert_eq!(detect(&samples(&[FACE_DOWN; 10]), &settings), None);
+
+        // turning slower than the flip time isn't
+        let slowly = Movements {
+            flip_time_ms: 100,
+            ..settings
+        };
+        assert_eq!(detect(&samples(&turning), &slowly), None);
+    }
+
+    #[test]
+    fn detector_detects_each_movement_once() {
+        let mut detector = Detector::new(&Movements::default());
+        let mut detected = vec![];
+        let g = ACCEL_RES_PER_G;
+        for i in 0..500u32 {
+            let mut state = testing::report(i);
+            state.motion.accel = if i < 250 { [0, g, 0] } else { [0, -g, 0] };
+            if i == 100 {
+                state.motion.accel = [0, 3 * g, 0];
+            }
+            detected.extend(detector.update(&state).map(|m| (i, m)));
+        }
+        assert_eq!(detected, [(101, Movement::Tap), (250, Movement::Flip)]);
+    }
+}
@@ -0,0 +1,260 @@
+use evdev_rs::enums::{EventCode, InputProp, EV_ABS, EV_KEY};
+
+use crate::{
+    hid::{Touch, TOUCHPAD_HEIGHT, TOUCHPAD_WIDTH},
+    mapping::{AbsRange, Capabilities},
+};
+
+/// Fingers the controller tracks, each in a slot of its own
+const SLOTS: usize = 2;
+/// Touchpad units per millimeter, which libinput needs to tell distances on the pad
+const RESOLUTION: i32 = 44;
+
+/// Codes of a touchpad device reporting the fingers with multi-touch protocol B, and the pad's
+/// click as the left button, as the kernel's hid-sony driver does
+pub fn capabilities() -> Capabilities {
+    let axis = |maximum: u16| AbsRange {
+        minimum: 0,
+        maximum: maximum as i32 - 1,
+        rest: 0,
+        fuzz: 0,
+        flat: 0,
+        resolution: RESOLUTION,
+    };
+    let (x, y) = (axis(TOUCHPAD_WIDTH), axis(TOUCHPAD_HEIGHT));
+    Capabilities {
+        keys: vec![
+            EV_KEY::BTN_LEFT,
+            EV_KEY::BTN_TOUCH,
+            EV_KEY::BTN_TOOL_FINGER,
+            EV_KEY::BTN_TOOL_DOUBLETAP,
+        ],
+        abs: vec![
+            (EV_ABS::ABS_X, x),
+            (EV_ABS::ABS_Y, y),
+            (
+                EV_ABS::ABS_MT_SLOT,
+                AbsRange {
+                    maximum: SLOTS as i32 - 1,
+                    resolution: 0,
+                    ..x
+                },
+            ),
+            (EV_ABS::ABS_MT_POSITION_X, x),
+            (EV_ABS::ABS_MT_POSITION_Y, y),
+            (
+                EV_ABS::ABS_MT_TRACKING_ID,
+                AbsRange {
+                    maximum: u16::MAX as i32,
+                    resolution: 0,
+                    ..x
+                },
+            ),
+        ],
+        rel: vec![],
+        msc: vec![],
+        props: vec![
+            InputProp::INPUT_PROP_POINTER,
+            InputProp::INPUT_PROP_BUTTONPAD,
+        ],
+    }
+}
+
+/// Finger in a slot, with the tracking id it's reported with
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Contact {
+    touch: Touch,
+    tracking_id: i32,
+}
+
+/// Turns the touchpad fingers of each controller report into multi-touch protocol B events
+#[derive(Debug, Clone, Default)]
+pub struct Contacts {
+    slots: [Option<Contact>; SLOTS],
+    /// Slot the device takes position events to be for
+    slot: usize,
+    next_tracking_id: i32,
+    /// Last value of the codes which aren't per slot
+    last: Vec<(EventCode, i32)>,
+}
+
+impl Contacts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events for fingers `touches` and the pad being `clicked`, only those that changed. Each
+    /// finger of the controller keeps its slot, and gets a new tracking id when put down.
+    pub fn report(
+        &mut self,
+        touches: &[Option<Touch>; SLOTS],
+        clicked: bool,
+    ) -> Vec<(EventCode, i32)> {
+        let mut events = vec![];
+        for (slot, touch) in touches.iter().enumerate() {
+            let last = self.slots[slot];
+            let mut changes = vec![];
+            match (last, touch) {
+                (None, None) => {}
+                (Some(_), None) => {
+                    changes.push((EV_ABS::ABS_MT_TRACKING_ID, -1));
+                    self.slots[slot] = None;
+                }
+                (last, Some(touch)) => {
+                    let contact = match last {
+                        Some(contact) if contact.touch.id == touch.id => contact,
+                        _ => {
+                            let tracking_id = self.next_tracking_id;
+                            self.next_tracking_id = (tracking_id + 1) % (u16::MAX as i32 + 1);
+                            changes.push((EV_ABS::ABS_MT_TRACKING_ID, tracking_id));
+                            Contact {
+                                // nothing was reported for it yet
+                                touch: Touch {
+                                    id: touch.id,
+                                    x: u16::MAX,
+                                    y: u16::MAX,
+                                },
+                                tracking_id,
+                            }
+                        }
+                    };
+                    if contact.touch.x != touch.x {
+                        changes.push((EV_ABS::ABS_MT_POSITION_X, touch.x as i32));
+                    }
+                    if contact.touch.y != touch.y {
+                        changes.push((EV_ABS::ABS_MT_POSITION_Y, touch.y as i32));
+                    }
+                    self.slots[slot] = Some(Contact {
+                        touch: *touch,
+                        ..contact
+                    });
+                }
+            }
+            if changes.is_empty() {
+                continue;
+            }
+            if self.slot != slot {
+                events.push((EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT), slot as i32));
+                self.slot = slot;
+            }
+            events.extend(
+                changes
+                    .into_iter()
+                    .map(|(code, value)| (EventCode::EV_ABS(code), value)),
+            );
+        }
+
+        let fingers = self.slots.iter().flatten().count();
+        let mut values = vec![
+            (EventCode::EV_KEY(EV_KEY::BTN_LEFT), clicked as i32),
+            (EventCode::EV_KEY(EV_KEY::BTN_TOUCH), (fingers > 0) as i32),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_TOOL_FINGER),
+                (fingers == 1) as i32,
+            ),
+            (
+                EventCode::EV_KEY(EV_KEY::BTN_TOOL_DOUBLETAP),
+                (fingers == 2) as i32,
+            ),
+        ];
+        // single touch pointer emulation follows the finger in the first slot in use
+        if let Some(contact) = self.slots.iter().flatten().next() {
+            values.push((EventCode::EV_ABS(EV_ABS::ABS_X), contact.touch.x as i32));
+            values.push((EventCode::EV_ABS(EV_ABS::ABS_Y), contact.touch.y as i32));
+        }
+        for (code, value) in values {
+            match self.last.iter_mut().find(|(c, _)| *c == code) {
+                Some((_, last)) if *last == value => {}
+                Some((_, last)) => {
+                    *last = value;
+                    events.push((code, value));
+                }
+                None => {
+                    self.last.push((code, value));
+                    // the device starts out with every code at zero
+                    if value != 0 {
+                        events.push((code, value));
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finger(id: u8, x: u16, y: u16) -> Option<Touch> {
+        Some(Touch { id, x, y })
+    }
+
+    fn abs(code: EV_ABS, value: i32) -> (EventCode, i32) {
+        (EventCode::EV_ABS(code), value)
+    }
+
+    fn key(code: EV_KEY, value: i32) -> (EventCode, i32) {
+        (EventCode::EV_KEY(code), value)
+    }
+
+    #[test]
+    fn fingers_are_reported_in_slots() {
+        let mut contacts = Contacts::new();
+        assert_eq!(contacts.report(&[None, None], false), []);
+        assert_eq!(
+            contacts.report(&[finger(7, 100, 200), None], false),
+            [
+                abs(EV_ABS::ABS_MT_TRACKING_ID, 0),
+                abs(EV_ABS::ABS_MT_POSITION_X, 100),
+                abs(EV_ABS::ABS_MT_POSITION_Y, 200),
+                key(EV_KEY::BTN_TOUCH, 1),
+                key(EV_KEY::BTN_TOOL_FINGER, 1),
+                abs(EV_ABS::ABS_X, 100),
+                abs(EV_ABS::ABS_Y, 200),
+            ]
+        );
+        assert_eq!(
+            contacts.report(&[finger(7, 100, 210), finger(8, 900, 500)], true),
+            [
+                abs(EV_ABS::ABS_MT_POSITION_Y, 210),
+                abs(EV_ABS::ABS_MT_SLOT, 1),
+                abs(EV_ABS::ABS_MT_TRACKING_ID, 1),
+                abs(EV_ABS::ABS_MT_POSITION_X, 900),
+                abs(EV_ABS::ABS_MT_POSITION_Y, 500),
+                key(EV_KEY::BTN_LEFT, 1),
+                key(EV_KEY::BTN_TOOL_FINGER, 0),
+                key(EV_KEY::BTN_TOOL_DOUBLETAP, 1),
+                abs(EV_ABS::ABS_Y, 210),
+            ]
+        );
+        assert_eq!(
+            contacts.report(&[None, finger(8, 900, 500)], false),
+            [
+                abs(EV_ABS::ABS_MT_SLOT, 0),
+                abs(EV_ABS::ABS_MT_TRACKING_ID, -1),
+                key(EV_KEY::BTN_LEFT, 0),
+                key(EV_KEY::BTN_TOOL_FINGER, 1),
+                key(EV_KEY::BTN_TOOL_DOUBLETAP, 0),
+                abs(EV_ABS::ABS_X, 900),
+                abs(EV_ABS::ABS_Y, 500),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_finger_in_a_slot_gets_a_new_tracking_id() {
+        let mut contacts = Contacts::new();
+        contacts.report(&[finger(1, 100, 100), None], false);
+        let events = contacts.report(&[finger(2, 100, 100), None], false);
+        // a new touch has its position, even where the last one was
+        assert_eq!(
+            events,
+            [
+                abs(EV_ABS::ABS_MT_TRACKING_ID, 1),
+                abs(EV_ABS::ABS_MT_POSITION_X, 100),
+                abs(EV_ABS::ABS_MT_POSITION_Y, 100),
+            ]
+        );
+    }
+}
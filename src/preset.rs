@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+
+use evdev_rs::enums::{BusType, EV_ABS, EV_KEY};
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::{
+    config::DEFAULT_PROFILE,
+    hid::{Button, ANALOG_MAX},
+    inherit,
+    mapping::{AbsRange, Analog, Binding, Input, Output},
+    profile::{Axes, Buttons, Profile},
+};
+
+/// Controller the virtual device presents itself as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// The driver's own layout, as it always was
+    #[default]
+    Generic,
+    /// Xbox 360 pad as created by the kernel's xpad driver
+    Xbox360,
+    /// DualShock 4 as created by the kernel's hid-sony driver
+    Ds4,
+}
+
+/// What the virtual device tells applications it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub name: &'static str,
+    pub bustype: BusType,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+impl Preset {
+    pub fn identity(&self) -> Identity {
+        match self {
+            Preset::Generic => Identity {
+                name: "Sony Dualshock Hackery",
+                bustype: BusType::BUS_USB,
+                vendor: 0x054c,
+                product: 0x05c4,
+                version: 0,
+            },
+            Preset::Xbox360 => Identity {
+                name: "Microsoft X-Box 360 pad",
+                bustype: BusType::BUS_USB,
+                vendor: 0x045e,
+                product: 0x028e,
+                version: 0x0114,
+            },
+            Preset::Ds4 => Identity {
+                name: "Sony Computer Entertainment Wireless Controller",
+                bustype: BusType::BUS_USB,
+                vendor: 0x054c,
+                product: 0x05c4,
+                version: 0x8111,
+            },
+        }
+    }
+
+    /// Bindings of the emulated controller, which profiles start from
+    pub fn profile(&self) -> Profile {
+        let trigger_axes = [
+            (
+                Input::Analog(Analog::L2),
+                Binding::new(Output::Abs(EV_ABS::ABS_Z)),
+            ),
+            (
+                Input::Analog(Analog::R2),
+                Binding::new(Output::Abs(EV_ABS::ABS_RZ)),
+            ),
+        ];
+        let axes = Axes {
+            left_x: EV_ABS::ABS_X,
+            left_y: EV_ABS::ABS_Y,
+            right_x: EV_ABS::ABS_RX,
+            right_y: EV_ABS::ABS_RY,
+            ..Axes::default()
+        };
+        // neither driver reports the touchpad click on the gamepad itself
+        let touchpad = (Input::Button(Button::Touchpad), Binding::new(Output::None));
+        match self {
+            Preset::Generic => Profile::default(),
+            Preset::Xbox360 => Profile {
+                // xpad's BTN_X & BTN_Y are BTN_NORTH & BTN_WEST
+                buttons: Buttons {
+                    square: EV_KEY::BTN_NORTH,
+                    triangle: EV_KEY::BTN_WEST,
+                    ..Buttons::default()
+                },
+                axes,
+                // the triggers are only axes
+                map: BTreeMap::from_iter(trigger_axes.into_iter().chain([
+                    touchpad,
+                    (Input::Button(Button::L2), Binding::new(Output::None)),
+                    (Input::Button(Button::R2), Binding::new(Output::None)),
+                ])),
+                ..Profile::default()
+            },
+            Preset::Ds4 => Profile {
+                axes,
+                map: BTreeMap::from_iter(trigger_axes.into_iter().chain([touchpad])),
+                ..Profile::default()
+            },
+        }
+    }
+
+    /// Range reported for the full stick axes
+    pub fn stick_range(&self) -> AbsRange {
+        match self {
+            Preset::Generic | Preset::Ds4 => AbsRange {
+                minimum: 0,
+                maximum: ANALOG_MAX as i32,
+                rest: 127,
+                fuzz: 0,
+                flat: 0,
+                resolution: 0,
+            },
+            Preset::Xbox360 => AbsRange {
+                minimum: i16::MIN as i32,
+                maximum: i16::MAX as i32,
+                rest: 0,
+                // xpad's, whatever `flat` the profile asks for
+                fuzz: 16,
+                flat: 128,
+                resolution: 0,
+            },
+        }
+    }
+
+    /// Stick `flat` of a profile, given in the range of the controller, in the reported range.
+    /// Presets copying a driver report its flat instead.
+    pub fn stick_flat(&self, flat: i32) -> i32 {
+        let range = self.stick_range();
+        match self {
+            Preset::Generic | Preset::Ds4 => {
+                let scale = (range.maximum - range.minimum) as f32 / ANALOG_MAX as f32;
+                (flat as f32 * scale).round() as i32
+            }
+            Preset::Xbox360 => range.flat,
+        }
+    }
+}
+
+/// Start every profile of `config` which doesn't extend another from the bindings of its
+/// `emulate` preset. Settings of the profile merge on top, like those of an extending profile.
+pub fn apply(config: &mut Table) {
+    let preset = config
+        .get("emulate")
+        .cloned()
+        .and_then(|preset| preset.try_into::<Preset>().ok())
+        .unwrap_or_default();
+    if preset == Preset::Generic {
+        // its bindings are the defaults already
+        return;
+    }
+    let base = Table::try_from(preset.profile()).expect("profiles serialize to tables");
+    let profiles = config.entry("profiles").or_insert_with(|| {
+        Value::Table(Table::from_iter([(
+            DEFAULT_PROFILE.to_string(),
+            Value::Table(Table::new()),
+        )]))
+    });
+    let Value::Table(profiles) = profiles else {
+        return;
+    };
+    for (_, profile) in profiles.iter_mut() {
+        let Value::Table(table) = profile else {
+            continue;
+        };
+        if table.contains_key("extends") {
+            continue;
+        }
+        let mut merged = base.clone();
+        inherit::merge(&mut merged, table);
+        *table = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use evdev_rs::enums::EventCode;
+
+    use super::*;
+    use crate::{config::Config, hid::DS4State, mapping::Mapping};
+
+    #[test]
+    fn xbox360_reports_signed_sticks_and_trigger_axes() {
+        let config = Config::parse("emulate = \"xbox360\"").unwrap();
+        let mut mapping = Mapping::new(&config.profiles["default"], config.emulate);
+        let capabilities = mapping.capabilities();
+        // the stick axes are xpad's, even though the profile has a flat of its own
+        assert_eq!(config.profiles["default"].left_stick.flat, 15);
+        for code in [EV_ABS::ABS_X, EV_ABS::ABS_Y, EV_ABS::ABS_RX, EV_ABS::ABS_RY] {
+            let stick = capabilities.range(code).unwrap();
+            assert_eq!(
+                (
+                    stick.minimum,
+                    stick.maximum,
+                    stick.rest,
+                    stick.fuzz,
+                    stick.flat
+                ),
+                (-32768, 32767, 0, 16, 128)
+            );
+        }
+        assert!(capabilities.range(EV_ABS::ABS_Z).is_some());
+        assert!(!capabilities.keys.contains(&EV_KEY::BTN_TL2));
+        assert!(!capabilities.keys.contains(&EV_KEY::BTN_TOUCH));
+
+        let mut state = DS4State::initial_state();
+        (state.lsx, state.lsy, state.rsx, state.rsy) = (0, 255, 127, 128);
+        state.r2_analog = 255;
+        let frame = mapping.frame(&state, Duration::ZERO);
+        let value = |code| {
+            frame
+                .values
+                .iter()
+                .find(|v| v.code == EventCode::EV_ABS(code))
+                .unwrap()
+                .value
+        };
+        assert_eq!(value(EV_ABS::ABS_X), -32768);
+        assert_eq!(value(EV_ABS::ABS_Y), 32767);
+        assert_eq!(value(EV_ABS::ABS_RX), 0);
+        assert_eq!(value(EV_ABS::ABS_RY), 0);
+        assert_eq!(value(EV_ABS::ABS_RZ), 255);
+    }
+
+    #[test]
+    fn profile_settings_override_the_preset() {
+        let config = Config::parse(
+            "emulate = \"ds4\"\n[profiles.game.buttons]\ncross = \"KEY_A\"\n[profiles.game.map]\ntouchpad = \"KEY_B\"\n",
+        )
+        .unwrap();
+        let game = &config.profiles["game"];
+        assert_eq!(game.axes.left_x, EV_ABS::ABS_X);
+        assert_eq!(game.buttons.cross, EV_KEY::KEY_A);
+        assert_eq!(
+            game.map[&Input::Button(Button::Touchpad)],
+            Binding::new(Output::Key(EV_KEY::KEY_B))
+        );
+        assert_eq!(
+            game.map[&Input::Analog(Analog::L2)],
+            Binding::new(Output::Abs(EV_ABS::ABS_Z))
+        );
+    }
+
+    #[test]
+    fn generic_preset_is_the_default() {
+        assert_eq!(Preset::default().profile(), Profile::default());
+        assert_eq!(
+            Config::parse("emulate = \"generic\"").unwrap(),
+            Config::default()
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::profile::Profile;
+
+/// How running applications are watched to pick a profile automatically
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoSwitch {
+    /// Shell command printing the focused application's pid or process name. When left out, every
+    /// running process is considered instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+    /// Milliseconds between checks
+    pub interval_ms: u64,
+}
+
+impl Default for AutoSwitch {
+    fn default() -> Self {
+        Self {
+            hook: None,
+            interval_ms: 1000,
+        }
+    }
+}
+
+/// Applications a profile is meant for. A profile without any is only activated by hand.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// Process names, as in `/proc/<pid>/comm` or the file name of the executable
+    pub process: Vec<String>,
+    /// Text contained in the process' command line
+    pub cmdline: Vec<String>,
+}
+
+impl Rule {
+    pub fn is_empty(&self) -> bool {
+        self.process.is_empty() && self.cmdline.is_empty()
+    }
+
+    pub fn matches(&self, process: &Process) -> bool {
+        self.process
+            .iter()
+            .any(|name| *name == process.name || Some(name.as_str()) == process.executable())
+            || self
+                .cmdline
+                .iter()
+                .any(|text| process.cmdline.contains(text.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    /// Arguments joined by spaces
+    pub cmdline: String,
+}
+
+impl Process {
+    /// File name of the executable, which unlike `name` isn't cut off at 15 characters
+    fn executable(&self) -> Option<&str> {
+        let program = self.cmdline.split(' ').next()?;
+        program.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+}
+
+/// Where the processes to match profiles against come from
+pub trait ProcessSource {
+    /// Processes in order of preference, the first one matching a profile decides
+    fn processes(&mut self) -> io::Result<Vec<Process>>;
+}
+
+/// Every running process, read from procfs
+#[derive(Debug, Clone)]
+pub struct Procfs {
+    root: PathBuf,
+}
+
+impl Procfs {
+    pub fn new() -> Self {
+        Self::at("/proc")
+    }
+
+    pub fn at(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn process(&self, pid: u32) -> Option<Process> {
+        read_process(&self.root.join(pid.to_string()), pid)
+    }
+}
+
+impl Default for Procfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessSource for Procfs {
+    fn processes(&mut self) -> io::Result<Vec<Process>> {
+        let mut pids: Vec<u32> = fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        // the most recently started application wins
+        pids.sort_unstable_by(|a, b| b.cmp(a));
+        // processes may exit while they're being read
+        Ok(pids
+            .into_iter()
+            .filter_map(|pid| self.process(pid))
+            .collect())
+    }
+}
+
+/// The focused application, as printed by a user provided command. This keeps the driver
+/// independent of X11 and the various Wayland compositors.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    command: String,
+    procfs: Procfs,
+}
+
+impl Hook {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            procfs: Procfs::new(),
+        }
+    }
+}
+
+impl ProcessSource for Hook {
+    fn processes(&mut self) -> io::Result<Vec<Process>> {
+        let output = Command::new("sh").arg("-c").arg(&self.command).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` failed with {}",
+                self.command, output.status
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let focused = stdout.trim();
+        if focused.is_empty() {
+            return Ok(vec![]);
+        }
+        let process = match focused.parse() {
+            Ok(pid) => self.procfs.process(pid),
+            Err(_) => Some(Process {
+                pid: 0,
+                name: focused.to_string(),
+                cmdline: focused.to_string(),
+            }),
+        };
+        Ok(process.into_iter().collect())
+    }
+}
+
+fn read_process(dir: &Path, pid: u32) -> Option<Process> {
+    let name = fs::read_to_string(dir.join("comm")).ok()?;
+    // arguments are nul terminated
+    let cmdline = fs::read(dir.join("cmdline")).ok()?;
+    let cmdline = String::from_utf8_lossy(&cmdline)
+        .split('\0')
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(Process {
+        pid,
+        name: name.trim_end().to_string(),
+        cmdline,
+    })
+}
+
+/// Name of the first profile whose rule matches one of `processes`, trying processes in order
+pub fn select<'a>(
+    profiles: &'a BTreeMap<String, Profile>,
+    processes: &[Process],
+) -> Option<&'a str> {
+    processes.iter().find_map(|process| {
+        profiles
+            .iter()
+            .find(|(_, profile)| profile.rule.matches(process))
+            .map(|(name, _)| name.as_str())
+    })
+}
+
+/// Picks the profile for the running applications, only reporting it when it changes, so
+/// profiles switched to by hand stay active until another application shows up.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    last: String,
+}
+
+impl Watcher {
+    /// Watcher starting out with the `active` profile, which isn't reported again
+    pub fn new(active: &str) -> Self {
+        Self {
+            last: active.to_string(),
+        }
+    }
+
+    /// Profile to switch to for `processes`, falling back to `default` when none matches
+    pub fn update(
+        &mut self,
+        profiles: &BTreeMap<String, Profile>,
+        default: &str,
+        processes: &[Process],
+    ) -> Option<String> {
+        let selected = select(profiles, processes).unwrap_or(default);
+        if self.last == selected {
+            return None;
+        }
+        self.last = selected.to_string();
+        Some(self.last.clone())
+    }
+}
+
+/// Read `source` every `interval` on a thread of its own, as reading it may be slow.
+/// Failures are logged and skipped.
+pub fn spawn(
+    mut source: impl ProcessSource + Send + 'static,
+    interval: Duration,
+) -> Receiver<Vec<Process>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        match source.processes() {
+            Ok(processes) => {
+                if sender.send(processes).is_err() {
+                    // nobody is listening anymore
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Could not list processes: {}", e),
+        }
+        thread::sleep(interval);
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeSource(Vec<Vec<Process>>);
+
+    impl ProcessSource for FakeSource {
+        fn processes(&mut self) -> io::Result<Vec<Process>> {
+            Ok(self.0.pop().unwrap_or_default())
+        }
+    }
+
+    fn process(name: &str, cmdline: &str) -> Process {
+        Process {
+            pid: 1,
+            name: name.to_string(),
+            cmdline: cmdline.to_string(),
+        }
+    }
+
+    fn profiles() -> BTreeMap<String, Profile> {
+        let mut retroarch = Profile::default();
+        retroarch.rule.process = vec!["retroarch".to_string()];
+        let mut steam = Profile::default();
+        steam.rule.cmdline = vec!["steamapps/common".to_string()];
+        BTreeMap::from([
+            ("default".to_string(), Profile::default()),
+            ("retroarch".to_string(), retroarch),
+            ("steam".to_string(), steam),
+        ])
+    }
+
+    #[test]
+    fn rules_match_name_executable_or_cmdline() {
+        let profiles = profiles();
+        let game = process("game.x86_64", "/home/me/steamapps/common/Game/game.x86_64");
+        assert_eq!(select(&profiles, &[game]), Some("steam"));
+        // comm is cut off at 15 characters, the executable isn't
+        let mut retroarch = profiles["retroarch"].clone();
+        retroarch.rule.process = vec!["retroarch-nightly".to_string()];
+        let nightly = process("retroarch-night", "/usr/bin/retroarch-nightly --fullscreen");
+        assert!(retroarch.rule.matches(&nightly));
+        assert_eq!(select(&profiles, &[process("bash", "bash")]), None);
+    }
+
+    #[test]
+    fn first_matching_process_decides() {
+        let processes = [process("bash", "bash"), process("retroarch", "retroarch")];
+        assert_eq!(select(&profiles(), &processes), Some("retroarch"));
+    }
+
+    #[test]
+    fn watcher_reports_changes_only() {
+        let profiles = profiles();
+        let mut watcher = Watcher::new("default");
+        // the profile the driver starts with isn't a change
+        assert_eq!(watcher.update(&profiles, "default", &[]), None);
+        let retroarch = [process("retroarch", "retroarch")];
+        assert_eq!(
+            watcher.update(&profiles, "default", &retroarch).as_deref(),
+            Some("retroarch")
+        );
+        assert_eq!(watcher.update(&profiles, "default", &retroarch), None);
+        assert_eq!(
+            watcher.update(&profiles, "default", &[]).as_deref(),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn spawned_source_is_polled() {
+        let source = FakeSource(vec![vec![process("retroarch", "retroarch")]]);
+        let receiver = spawn(source, Duration::from_millis(1));
+        assert_eq!(receiver.recv().unwrap()[0].name, "retroarch");
+        assert!(receiver.recv().unwrap().is_empty());
+    }
+
+    #[test]
+    fn procfs_is_read_newest_first() {
+        let root = std::env::temp_dir().join(format!("ds4linux-procfs-{}", std::process::id()));
+        for (pid, comm, cmdline) in [
+            ("7", "bash\n", "bash\0"),
+            ("42", "retroarch\n", "/usr/bin/retroarch\0-f\0"),
+        ] {
+            fs::create_dir_all(root.join(pid)).unwrap();
+            fs::write(root.join(pid).join("comm"), comm).unwrap();
+            fs::write(root.join(pid).join("cmdline"), cmdline).unwrap();
+        }
+        fs::create_dir_all(root.join("self")).unwrap();
+        let processes = Procfs::at(&root).processes();
+        fs::remove_dir_all(&root).unwrap();
+
+        let processes = processes.unwrap();
+        assert_eq!(processes.len(), 2);
+        assert_eq!(
+            processes[0],
+            process("retroarch", "/usr/bin/retroarch -f").with_pid(42)
+        );
+        assert_eq!(processes[1].name, "bash");
+    }
+
+    impl Process {
+        fn with_pid(mut self, pid: u32) -> Self {
+            self.pid = pid;
+            self
+        }
+    }
+}
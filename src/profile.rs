@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+
+use evdev_rs::enums::{EV_ABS, EV_KEY};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    curve::Curve,
+    flick::FlickStick,
+    gyro::Gyro,
+    hid::{Button, ANALOG_MAX},
+    mapping::{Binding, Input},
+    movement::Movements,
+    process::Rule,
+    tilt::Tilt,
+    touchpad::Touchpad,
+};
+
+/// Which event every controller input is reported as, and tunable settings of the analog sticks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Profile whose settings this one starts from, see [`crate::inherit`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Lightbar color while the profile is active
+    pub lightbar: [u8; 3],
+    /// Applications the profile is activated for automatically
+    #[serde(rename = "match", skip_serializing_if = "Rule::is_empty")]
+    pub rule: Rule,
+    pub buttons: Buttons,
+    pub axes: Axes,
+    pub left_stick: Stick,
+    pub right_stick: Stick,
+    pub touchpad: Touchpad,
+    pub gyro: Gyro,
+    pub flick_stick: FlickStick,
+    pub tilt: Tilt,
+    pub movements: Movements,
+    /// Bindings replacing those of `buttons` & `axes`, see [`crate::mapping`]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub map: BTreeMap<Input, Binding>,
+}
+
+/// Key code reported for each button
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Buttons {
+    #[serde(with = "code")]
+    pub square: EV_KEY,
+    #[serde(with = "code")]
+    pub cross: EV_KEY,
+    #[serde(with = "code")]
+    pub circle: EV_KEY,
+    #[serde(with = "code")]
+    pub triangle: EV_KEY,
+    #[serde(with = "code")]
+    pub l1: EV_KEY,
+    #[serde(with = "code")]
+    pub l2: EV_KEY,
+    #[serde(with = "code")]
+    pub l3: EV_KEY,
+    #[serde(with = "code")]
+    pub r1: EV_KEY,
+    #[serde(with = "code")]
+    pub r2: EV_KEY,
+    #[serde(with = "code")]
+    pub r3: EV_KEY,
+    #[serde(with = "code")]
+    pub select: EV_KEY,
+    #[serde(with = "code")]
+    pub start: EV_KEY,
+    #[serde(with = "code")]
+    pub ps: EV_KEY,
+    #[serde(with = "code")]
+    pub touchpad: EV_KEY,
+}
+
+/// Absolute axis code reported for the dpad & sticks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Axes {
+    #[serde(with = "code")]
+    pub dpad_x: EV_ABS,
+    #[serde(with = "code")]
+    pub dpad_y: EV_ABS,
+    #[serde(with = "code")]
+    pub left_x: EV_ABS,
+    #[serde(with = "code")]
+    pub left_y: EV_ABS,
+    #[serde(with = "code")]
+    pub right_x: EV_ABS,
+    #[serde(with = "code")]
+    pub right_y: EV_ABS,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stick {
+    /// `flat` value reported to the virtual device, i.e. the deadzone applications are told to use
+    pub flat: i32,
+    pub x: Axis,
+    pub y: Axis,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Axis {
+    /// Distance from center in raw units within which the axis reports center. `0` disables it.
+    pub deadzone: u8,
+    pub curve: Curve,
+    /// Fraction of full deflection reported as soon as the axis leaves center, to make up for
+    /// deadzones in games
+    pub anti_deadzone: f32,
+    /// Multiplier of the distance from center, after the curve
+    pub sensitivity: f32,
+}
+
+impl Default for Axis {
+    fn default() -> Self {
+        Self {
+            deadzone: 0,
+            curve: Curve::default(),
+            anti_deadzone: 0.0,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl Default for Buttons {
+    fn default() -> Self {
+        Self {
+            square: EV_KEY::BTN_WEST,
+            cross: EV_KEY::BTN_SOUTH,
+            circle: EV_KEY::BTN_EAST,
+            triangle: EV_KEY::BTN_NORTH,
+            l1: EV_KEY::BTN_TL,
+            l2: EV_KEY::BTN_TL2,
+            l3: EV_KEY::BTN_THUMBL,
+            r1: EV_KEY::BTN_TR,
+            r2: EV_KEY::BTN_TR2,
+            r3: EV_KEY::BTN_THUMBR,
+            select: EV_KEY::BTN_SELECT,
+            start: EV_KEY::BTN_START,
+            ps: EV_KEY::BTN_MODE,
+            touchpad: EV_KEY::BTN_TOUCH,
+        }
+    }
+}
+
+impl Default for Axes {
+    fn default() -> Self {
+        Self {
+            dpad_x: EV_ABS::ABS_HAT0X,
+            dpad_y: EV_ABS::ABS_HAT0Y,
+            left_x: EV_ABS::ABS_TILT_X,
+            left_y: EV_ABS::ABS_TILT_Y,
+            right_x: EV_ABS::ABS_RX,
+            right_y: EV_ABS::ABS_RY,
+        }
+    }
+}
+
+impl Buttons {
+    /// Key code reported for `button`, `None` for the dpad which is a hat
+    pub fn code(&self, button: Button) -> Option<EV_KEY> {
+        Some(match button {
+            Button::Square => self.square,
+            Button::Cross => self.cross,
+            Button::Circle => self.circle,
+            Button::Triangle => self.triangle,
+            Button::L1 => self.l1,
+            Button::L2 => self.l2,
+            Button::L3 => self.l3,
+            Button::R1 => self.r1,
+            Button::R2 => self.r2,
+            Button::R3 => self.r3,
+            Button::Select => self.select,
+            Button::Start => self.start,
+            Button::Ps => self.ps,
+            Button::Touchpad => self.touchpad,
+            Button::Left | Button::Up | Button::Right | Button::Down => return None,
+        })
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            extends: None,
+            lightbar: [0, 0, 64],
+            rule: Rule::default(),
+            buttons: Buttons::default(),
+            axes: Axes::default(),
+            left_stick: Stick {
+                flat: 15,
+                x: Axis {
+                    curve: Curve::Custom,
+                    ..Axis::default()
+                },
+                y: Axis {
+                    curve: Curve::Custom,
+                    ..Axis::default()
+                },
+            },
+            right_stick: Stick {
+                flat: 5,
+                x: Axis {
+                    deadzone: 100,
+                    ..Axis::default()
+                },
+                y: Axis::default(),
+            },
+            touchpad: Touchpad::default(),
+            gyro: Gyro::default(),
+            flick_stick: FlickStick::default(),
+            tilt: Tilt::default(),
+            movements: Movements::default(),
+            map: BTreeMap::new(),
+        }
+    }
+}
+
+impl Axis {
+    /// Return the value to report for raw axis value `x`, after deadzone, curve, sensitivity and
+    /// anti-deadzone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ds4linux::profile::Axis;
+    ///
+    /// let axis = Axis { deadzone: 100, ..Axis::default() };
+    /// assert_eq!(axis.apply(60), 127);
+    /// assert_eq!(axis.apply(250), 250);
+    /// assert_eq!(axis.apply(20), 20);
+    ///
+    /// let axis = Axis { deadzone: 10, anti_deadzone: 0.2, ..Axis::default() };
+    /// assert_eq!(axis.apply(140), 163);
+    /// assert_eq!(axis.apply(255), 255);
+    /// ```
+    pub fn apply(&self, x: u8) -> i32 {
+        if self.deadzone > 0 && i16::abs(x as i16 - 128) <= self.deadzone as i16 {
+            return 127; // center stick value
+        }
+        let value = self.curve.apply(x, ANALOG_MAX);
+        if self.sensitivity == 1.0 && self.anti_deadzone == 0.0 {
+            return value as i32;
+        }
+        let middle = ANALOG_MAX as f32 / 2.0;
+        // the center is between two raw values, either of them is at rest
+        if (value - middle).abs() <= 0.5 {
+            return value as i32;
+        }
+        let offset = ((value - middle) / middle * self.sensitivity).clamp(-1.0, 1.0);
+        let magnitude = self.anti_deadzone + (1.0 - self.anti_deadzone) * offset.abs();
+        (middle + magnitude.copysign(offset) * middle).round() as i32
+    }
+}
+
+/// (De)serialize evdev codes by their kernel name, e.g. `"BTN_SOUTH"`
+pub(crate) mod code {
+    use std::fmt::Debug;
+
+    use serde::{
+        de::{value, DeserializeOwned, Error, IntoDeserializer},
+        Deserialize, Deserializer, Serializer,
+    };
+
+    /// Kernel names that are aliases of another code, and so have no variant of their own
+    const ALIASES: [(&str, &str); 4] = [
+        ("BTN_A", "BTN_SOUTH"),
+        ("BTN_B", "BTN_EAST"),
+        ("BTN_X", "BTN_NORTH"),
+        ("BTN_Y", "BTN_WEST"),
+    ];
+
+    pub fn serialize<S: Serializer, T: Debug>(code: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        // evdev-rs names its variants after the kernel names, and unlike `Display` debug
+        // formatting doesn't need libevdev
+        serializer.serialize_str(&format!("{:?}", code))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: DeserializeOwned>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        parse(&name).ok_or_else(|| D::Error::custom(format!("unknown event code `{}`", name)))
+    }
+
+    /// Code of type `T` named `name`, if there is one
+    pub fn parse<T: DeserializeOwned>(name: &str) -> Option<T> {
+        let canonical = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, canonical)| canonical);
+        let variant: value::StrDeserializer<value::Error> = canonical.into_deserializer();
+        T::deserialize(variant).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_profile_roundtrips_through_toml() {
+        let profile = Profile::default();
+        let serialized = toml::to_string_pretty(&profile).unwrap();
+        assert_eq!(toml::from_str::<Profile>(&serialized).unwrap(), profile);
+    }
+
+    #[test]
+    fn event_codes_are_read_by_name() {
+        let profile: Profile =
+            toml::from_str("[buttons]\ncross = \"KEY_ENTER\"\n[axes]\nleft_x = \"ABS_X\"").unwrap();
+        assert_eq!(profile.buttons.cross, EV_KEY::KEY_ENTER);
+        assert_eq!(profile.buttons.circle, EV_KEY::BTN_EAST);
+        assert_eq!(profile.axes.left_x, EV_ABS::ABS_X);
+    }
+
+    #[test]
+    fn event_code_aliases_are_accepted() {
+        let profile: Profile = toml::from_str("[buttons]\ncross = \"BTN_A\"").unwrap();
+        assert_eq!(profile.buttons.cross, EV_KEY::BTN_SOUTH);
+    }
+
+    #[test]
+    fn unknown_event_code_is_rejected() {
+        let err = toml::from_str::<Profile>("[buttons]\ncross = \"BTN_NOPE\"").unwrap_err();
+        assert!(err.message().contains("unknown event code `BTN_NOPE`"));
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let profile: Profile = toml::from_str("[right_stick.x]\ndeadzone = 10").unwrap();
+        assert_eq!(profile.right_stick.x.deadzone, 10);
+        assert_eq!(profile.right_stick.x.curve, Curve::Linear);
+        assert_eq!(profile.left_stick, Profile::default().left_stick);
+    }
+
+    #[test]
+    fn points_curve_is_read_from_toml() {
+        let profile: Profile =
+            toml::from_str("[left_stick.y]\ncurve = { points = [[0.5, 0.25], [1.0, 1.0]] }")
+                .unwrap();
+        assert_eq!(
+            profile.left_stick.y.curve,
+            Curve::Points(vec![[0.5, 0.25], [1.0, 1.0]])
+        );
+    }
+
+    #[test]
+    fn resting_stick_stays_centered() {
+        let anti_deadzone = Axis {
+            anti_deadzone: 0.2,
+            ..Axis::default()
+        };
+        let sensitive = Axis {
+            sensitivity: 1.5,
+            ..Axis::default()
+        };
+        for axis in [anti_deadzone, sensitive] {
+            assert_eq!(axis.apply(127), 127);
+            assert_eq!(axis.apply(128), 128);
+            assert_eq!(axis.apply(0), 0);
+            assert_eq!(axis.apply(255), 255);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use inotify::{Inotify, WatchMask};
+
+use crate::config::Config;
+
+/// Watch the config file at `path`, sending every version of it once it's written, or why it
+/// isn't valid, which leaves the last valid one to stay in use
+pub fn watch(path: &Path) -> io::Result<Receiver<Result<Config>>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("{} is not a file", path.display())))?
+        .to_owned();
+
+    let mut inotify = Inotify::init()?;
+    // editors tend to write a new file and rename it over the old one, which a watch on the
+    // file itself would lose track of
+    inotify
+        .watches()
+        .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+    let path = path.to_owned();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(e) => {
+                    let _ = sender.send(Err(anyhow!("Stopped watching {}: {}", path.display(), e)));
+                    return;
+                }
+            };
+            // a single save shows up as several events
+            if !events
+                .into_iter()
+                .any(|event| event.name == Some(file_name.as_os_str()))
+            {
+                continue;
+            }
+            if sender.send(Config::load(&path)).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn valid_changes_are_sent() {
+        let dir = std::env::temp_dir().join(format!("ds4linux-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let receiver = watch(&path).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        fs::write(dir.join("other.toml"), "not a config").unwrap();
+        fs::write(&path, "[profiles.default.right_stick.x]\ndeadzone = 200\n").unwrap();
+        let invalid = receiver.recv_timeout(timeout);
+        // replaced like editors do, so the file is never seen half written
+        let new = dir.join("config.toml.new");
+        fs::write(&new, "[profiles.default.right_stick.x]\ndeadzone = 20\n").unwrap();
+        fs::rename(&new, &path).unwrap();
+        let config = receiver.recv_timeout(timeout);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(invalid.unwrap().is_err());
+        let config = config.unwrap().unwrap();
+        assert_eq!(config.profiles["default"].right_stick.x.deadzone, 20);
+    }
+}
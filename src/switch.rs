@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hid::{Button, DS4State};
+
+/// Button chord switching between profiles: while `hold` is held, pressing `next` or `previous`
+/// activates the neighbouring profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Chord {
+    pub hold: Button,
+    pub next: Button,
+    pub previous: Button,
+}
+
+impl Default for Chord {
+    fn default() -> Self {
+        Self {
+            hold: Button::Ps,
+            next: Button::Right,
+            previous: Button::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cycle {
+    Next,
+    Previous,
+}
+
+/// Watches controller state for the profile switching [`Chord`]
+#[derive(Debug, Clone)]
+pub struct Switcher {
+    chord: Chord,
+    next_pressed: bool,
+    previous_pressed: bool,
+    /// Buttons of a completed chord, masked until they're released
+    latched: Vec<Button>,
+}
+
+impl Switcher {
+    pub fn new(chord: Chord) -> Self {
+        Self {
+            chord,
+            next_pressed: false,
+            previous_pressed: false,
+            latched: Vec::new(),
+        }
+    }
+
+    /// Feed unmasked controller state, returning which way to switch when the chord was just completed
+    pub fn update(&mut self, state: &DS4State) -> Option<Cycle> {
+        let holding = state.pressed(self.chord.hold);
+        let next = holding && state.pressed(self.chord.next);
+        let previous = holding && state.pressed(self.chord.previous);
+        let cycle = if next && !self.next_pressed {
+            Some(Cycle::Next)
+        } else if previous && !self.previous_pressed {
+            Some(Cycle::Previous)
+        } else {
+            None
+        };
+        self.next_pressed = next;
+        self.previous_pressed = previous;
+        self.latched.retain(|button| state.pressed(*button));
+        if let Some(cycle) = cycle {
+            let pressed = match cycle {
+                Cycle::Next => self.chord.next,
+                Cycle::Previous => self.chord.previous,
+            };
+            self.latched.extend([self.chord.hold, pressed]);
+        }
+        cycle
+    }
+
+    /// Release the `next` & `previous` buttons while `hold` is held, and every button of a
+    /// completed chord until it's let go, so games don't see them
+    pub fn mask(&self, state: &mut DS4State) {
+        if state.pressed(self.chord.hold) {
+            state.set_pressed(self.chord.next, false);
+            state.set_pressed(self.chord.previous, false);
+        }
+        for button in &self.latched {
+            state.set_pressed(*button, false);
+        }
+    }
+}
+
+/// Name following `current` in `names` when cycling in `direction`, wrapping around at the ends
+///
+/// # Examples
+///
+/// ```
+/// use ds4linux::switch::{cycle, Cycle};
+///
+/// let names = ["desktop", "retroarch", "steam"];
+/// assert_eq!(cycle(names, "retroarch", Cycle::Next), Some("steam"));
+/// assert_eq!(cycle(names, "steam", Cycle::Next), Some("desktop"));
+/// assert_eq!(cycle(names, "desktop", Cycle::Previous), Some("steam"));
+/// ```
+pub fn cycle<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    current: &str,
+    direction: Cycle,
+) -> Option<&'a str> {
+    let names: Vec<&str> = names.into_iter().collect();
+    let position = names.iter().position(|name| *name == current).unwrap_or(0);
+    let index = match direction {
+        Cycle::Next => (position + 1) % names.len().max(1),
+        Cycle::Previous => (position + names.len().max(1) - 1) % names.len().max(1),
+    };
+    names.get(index).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(pressed: &[Button]) -> DS4State {
+        let mut state = DS4State::initial_state();
+        for button in pressed {
+            state.set_pressed(*button, true);
+        }
+        state
+    }
+
+    #[test]
+    fn chord_triggers_once_per_press() {
+        let mut switcher = Switcher::new(Chord::default());
+        assert_eq!(switcher.update(&state(&[Button::Ps])), None);
+        assert_eq!(
+            switcher.update(&state(&[Button::Ps, Button::Right])),
+            Some(Cycle::Next)
+        );
+        assert_eq!(switcher.update(&state(&[Button::Ps, Button::Right])), None);
+        assert_eq!(switcher.update(&state(&[Button::Ps])), None);
+        assert_eq!(
+            switcher.update(&state(&[Button::Ps, Button::Left])),
+            Some(Cycle::Previous)
+        );
+    }
+
+    #[test]
+    fn chord_needs_hold_button() {
+        let mut switcher = Switcher::new(Chord::default());
+        assert_eq!(switcher.update(&state(&[Button::Right])), None);
+        // the chord is complete no matter which of its buttons is pressed last
+        assert_eq!(
+            switcher.update(&state(&[Button::Right, Button::Ps])),
+            Some(Cycle::Next)
+        );
+    }
+
+    #[test]
+    fn chord_buttons_are_masked_while_holding() {
+        let switcher = Switcher::new(Chord::default());
+        let mut holding = state(&[Button::Ps, Button::Right, Button::Cross]);
+        switcher.mask(&mut holding);
+        assert!(holding.ps && holding.cross && !holding.right);
+
+        let mut not_holding = state(&[Button::Right]);
+        switcher.mask(&mut not_holding);
+        assert!(not_holding.right);
+
+        // after the chord, its buttons stay masked until they're released
+        let mut switcher = Switcher::new(Chord::default());
+        let masked = |switcher: &mut Switcher, pressed: &[Button]| {
+            let mut state = state(pressed);
+            switcher.update(&state);
+            switcher.mask(&mut state);
+            state
+        };
+        assert_eq!(
+            switcher.update(&state(&[Button::Ps, Button::Right])),
+            Some(Cycle::Next)
+        );
+        // letting go of the hold button first doesn't leak the other one to games
+        let released = masked(&mut switcher, &[Button::Right]);
+        assert!(!released.right);
+        let released = masked(&mut switcher, &[Button::Right, Button::Cross]);
+        assert!(!released.right && released.cross);
+        // nor does the hold button show up once the other is let go
+        let mut switcher = Switcher::new(Chord::default());
+        masked(&mut switcher, &[Button::Ps, Button::Left]);
+        let released = masked(&mut switcher, &[Button::Ps]);
+        assert!(!released.ps);
+        // until each has been released
+        masked(&mut switcher, &[]);
+        let pressed = masked(&mut switcher, &[Button::Right]);
+        assert!(pressed.right);
+        let pressed = masked(&mut switcher, &[Button::Ps]);
+        assert!(pressed.ps);
+    }
+}
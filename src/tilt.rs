@@ -0,0 +1,153 @@
+use evdev_rs::enums::EV_ABS;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    curve::Curve,
+    fusion::{Fusion, Orientation},
+    hid::{Button, DS4State},
+    motion::SensorClock,
+    profile::code,
+};
+
+/// Tilt steering settings of a profile: leaning the controller like a steering wheel deflects
+/// an axis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tilt {
+    pub enabled: bool,
+    #[serde(with = "code")]
+    pub axis: EV_ABS,
+    /// Degrees of leaning which fully deflect the axis
+    pub max_angle: f32,
+    /// Degrees of leaning around the center which report center
+    pub deadzone: f32,
+    pub curve: Curve,
+    /// Button making the controller's current lean the center while held
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recenter: Option<Button>,
+}
+
+impl Default for Tilt {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            axis: EV_ABS::ABS_WHEEL,
+            max_angle: 45.0,
+            deadzone: 2.0,
+            curve: Curve::Linear,
+            recenter: None,
+        }
+    }
+}
+
+/// Turns the controller's roll into the deflection of a steering axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct Steering {
+    settings: Tilt,
+    orientation: Orientation,
+    clock: SensorClock,
+    /// Roll in degrees which reports center
+    center: f32,
+}
+
+impl Steering {
+    pub fn new(settings: &Tilt) -> Self {
+        Self {
+            settings: settings.clone(),
+            orientation: Orientation::new(&Fusion::default()),
+            clock: SensorClock::new(),
+            center: 0.0,
+        }
+    }
+
+    /// Offset of the steering axis from center for `state`, from `-1.0` (left) to `1.0` (right)
+    pub fn update(&mut self, state: &DS4State) -> f32 {
+        let time = self.clock.tick(state);
+        self.orientation.update(&state.motion, time);
+
+        let roll = self.roll();
+        if self
+            .settings
+            .recenter
+            .is_some_and(|button| state.pressed(button))
+        {
+            self.center = roll;
+        }
+        let settings = &self.settings;
+        let angle = roll - self.center;
+        if angle.abs() <= settings.deadzone {
+            return 0.0;
+        }
+        let range = (settings.max_angle - settings.deadzone).max(f32::EPSILON);
+        let deflection = ((angle.abs() - settings.deadzone) / range).min(1.0);
+        settings.curve.normalized(deflection.copysign(angle))
+    }
+
+    /// Degrees the controller leans to the right, however far it's tilted towards the player
+    pub fn roll(&self) -> f32 {
+        let [x, y, z] = self.orientation.gravity();
+        x.atan2(y.hypot(z)).to_degrees()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{testing, Motion, ACCEL_RES_PER_G};
+
+    /// State `report` reports in, leaning `degrees` to the right
+    fn leaning(report: u32, degrees: f32) -> DS4State {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut state = testing::report(report);
+        // at rest the accelerometer reads up, away from gravity
+        state.motion = Motion {
+            gyro: [0; 3],
+            accel: [-sin, cos, 0.0].map(|a| (a * ACCEL_RES_PER_G as f32) as i32),
+        };
+        state
+    }
+
+    /// Offset after holding the controller `degrees` to the right for several seconds
+    fn settle(steering: &mut Steering, reports: &mut u32, degrees: f32) -> f32 {
+        let mut offset = 0.0;
+        for _ in 0..2500 {
+            *reports += 1;
+            offset = steering.update(&leaning(*reports, degrees));
+        }
+        offset
+    }
+
+    #[test]
+    fn leaning_deflects_the_axis() {
+        let mut steering = Steering::new(&Tilt {
+            enabled: true,
+            deadzone: 0.0,
+            ..Tilt::default()
+        });
+        let mut reports = 0;
+        let right = settle(&mut steering, &mut reports, 22.5);
+        assert!((right - 0.5).abs() < 0.02, "{}", right);
+        let left = settle(&mut steering, &mut reports, -60.0);
+        assert_eq!(left, -1.0);
+    }
+
+    #[test]
+    fn deadzone_and_recenter() {
+        let mut steering = Steering::new(&Tilt {
+            enabled: true,
+            recenter: Some(Button::L3),
+            ..Tilt::default()
+        });
+        let mut reports = 0;
+        assert_eq!(settle(&mut steering, &mut reports, 1.5), 0.0);
+
+        let held = settle(&mut steering, &mut reports, 10.0);
+        assert!(held > 0.1, "{}", held);
+        reports += 1;
+        let mut state = leaning(reports, 10.0);
+        state.l3 = true;
+        assert_eq!(steering.update(&state), 0.0);
+        let recentered = settle(&mut steering, &mut reports, 10.0);
+        assert_eq!(recentered, 0.0);
+    }
+}
@@ -0,0 +1,594 @@
+use std::time::Duration;
+
+use evdev_rs::enums::{EV_ABS, EV_KEY, EV_REL};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    curve::Curve,
+    gesture::Gestures,
+    hid::{Touch, TOUCHPAD_HEIGHT, TOUCHPAD_WIDTH},
+    profile::code,
+};
+
+/// Finger motion per report, in touchpad units, from which the acceleration curve is at its end
+pub const ACCELERATION_SPEED: f32 = 40.0;
+/// High resolution wheel motion of one notch of a regular wheel
+const WHEEL_NOTCH: f32 = 120.0;
+
+/// What the touchpad is used as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Only its click is reported, as the `touchpad` button
+    #[default]
+    Button,
+    /// Like a laptop's: fingers move the pointer & scroll, taps & the click are mouse buttons
+    Trackpad,
+    /// A third analog stick, deflected by moving a finger, see [`TouchStick`]
+    Stick,
+}
+
+/// Where the center of a touchpad stick is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    /// Where the finger was put down
+    #[default]
+    Touch,
+    /// The center of the pad
+    Center,
+}
+
+/// Settings of the touchpad used as an analog stick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TouchStick {
+    pub origin: Origin,
+    #[serde(with = "code")]
+    pub x: EV_ABS,
+    #[serde(with = "code")]
+    pub y: EV_ABS,
+    /// Distance from the origin in touchpad units of full deflection
+    pub radius: u16,
+    /// Fraction of `radius` around the origin which reports center
+    pub deadzone: f32,
+    pub curve: Curve,
+}
+
+impl Default for TouchStick {
+    fn default() -> Self {
+        Self {
+            origin: Origin::Touch,
+            x: EV_ABS::ABS_RUDDER,
+            y: EV_ABS::ABS_THROTTLE,
+            radius: 300,
+            deadzone: 0.1,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+/// Ready made region layouts, numbered left to right & top to bottom
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layout {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "halves")]
+    Halves,
+    #[serde(rename = "2x2")]
+    Grid2x2,
+    #[serde(rename = "3x2")]
+    Grid3x2,
+}
+
+/// Parts of the touchpad which are buttons of their own, either a [`Layout`] or a list of
+/// rectangles given as `[left, top, right, bottom]` fractions of the pad
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Regions {
+    Layout(Layout),
+    Rects(Vec<[f32; 4]>),
+}
+
+impl Default for Regions {
+    fn default() -> Self {
+        Regions::Layout(Layout::None)
+    }
+}
+
+impl Regions {
+    /// Rectangle of every region, in the order they're numbered
+    pub fn rects(&self) -> Vec<[f32; 4]> {
+        let (columns, rows) = match self {
+            Regions::Rects(rects) => return rects.clone(),
+            Regions::Layout(Layout::None) => return vec![],
+            Regions::Layout(Layout::Halves) => (2, 1),
+            Regions::Layout(Layout::Grid2x2) => (2, 2),
+            Regions::Layout(Layout::Grid3x2) => (3, 2),
+        };
+        let (width, height) = (1.0 / columns as f32, 1.0 / rows as f32);
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    let (left, top) = (column as f32 * width, row as f32 * height);
+                    [left, top, left + width, top + height]
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether `touch` is within `rect`, given as `[left, top, right, bottom]` fractions of the pad
+pub fn contains(rect: &[f32; 4], touch: &Touch) -> bool {
+    let [left, top, right, bottom] = *rect;
+    let x = touch.x as f32 / TOUCHPAD_WIDTH as f32;
+    let y = touch.y as f32 / TOUCHPAD_HEIGHT as f32;
+    (left..right).contains(&x) && (top..bottom).contains(&y)
+}
+
+/// Touchpad settings of a profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Touchpad {
+    pub mode: Mode,
+    /// Pointer motion per touchpad unit of finger motion, the pad being
+    /// [`crate::hid::TOUCHPAD_WIDTH`] units wide
+    pub sensitivity: f32,
+    /// Acceleration, pointer speed by finger speed up to [`ACCELERATION_SPEED`]
+    pub curve: Curve,
+    /// High resolution wheel motion per touchpad unit of two finger motion, where 120 is a notch.
+    /// Negative for natural scrolling.
+    pub scroll_speed: f32,
+    /// Longest a touch lasts to be a tap
+    pub tap_time_ms: u64,
+    /// Farthest a finger moves in touchpad units during a tap
+    pub tap_distance: u16,
+    /// Parts of the pad bindable as `region1_click`, `region1_touch`, `region2_click`, ...
+    pub regions: Regions,
+    pub gestures: Gestures,
+    pub stick: TouchStick,
+}
+
+impl Default for Touchpad {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Button,
+            sensitivity: 0.5,
+            curve: Curve::Linear,
+            scroll_speed: 2.0,
+            tap_time_ms: 180,
+            tap_distance: 60,
+            regions: Regions::default(),
+            gestures: Gestures::default(),
+            stick: TouchStick::default(),
+        }
+    }
+}
+
+/// Mouse buttons & motion of the touchpad for one controller state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pointer {
+    /// Buttons held for this report
+    pub buttons: Vec<EV_KEY>,
+    pub motion: Vec<(EV_REL, f32)>,
+}
+
+/// Fingers on the pad from when the first was put down until all are lifted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub start: Duration,
+    /// Most fingers on the pad at once
+    pub fingers: usize,
+    /// Where each finger was put down, and where it was last
+    pub paths: Vec<(Touch, Touch)>,
+}
+
+impl Session {
+    /// Take in fingers `down` at `time`, starting `session` when the first is put down. Lifting
+    /// the last ends it, which returns it.
+    pub fn track(session: &mut Option<Session>, down: &[Touch], time: Duration) -> Option<Session> {
+        if down.is_empty() {
+            return session.take();
+        }
+        let session = session.get_or_insert_with(|| Session {
+            start: time,
+            fingers: 0,
+            paths: vec![],
+        });
+        session.fingers = session.fingers.max(down.len());
+        for touch in down {
+            match session.paths.iter_mut().find(|(o, _)| o.id == touch.id) {
+                Some((_, last)) => *last = *touch,
+                None => session.paths.push((*touch, *touch)),
+            }
+        }
+        None
+    }
+
+    /// How long the fingers have been down at `time`
+    pub fn duration(&self, time: Duration) -> Duration {
+        time.saturating_sub(self.start)
+    }
+
+    /// Distance of the finger farthest from where it was put down, in touchpad units
+    pub fn moved(&self) -> f32 {
+        self.paths
+            .iter()
+            .map(|(origin, last)| distance(origin, last))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Distance between fingers `a` & `b` in touchpad units
+pub fn distance(a: &Touch, b: &Touch) -> f32 {
+    (a.x as f32 - b.x as f32).hypot(a.y as f32 - b.y as f32)
+}
+
+/// Turns touchpad fingers into mouse input, like a laptop's trackpad
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trackpad {
+    settings: Touchpad,
+    last: [Option<Touch>; 2],
+    session: Option<Session>,
+    /// Whether the session can still be a tap, which it isn't once a finger moved or the pad
+    /// was clicked
+    tap: bool,
+}
+
+impl Trackpad {
+    /// Mouse buttons a trackpad reports
+    pub const KEYS: [EV_KEY; 2] = [EV_KEY::BTN_LEFT, EV_KEY::BTN_RIGHT];
+    /// Relative axes a trackpad reports
+    pub const RELS: [EV_REL; 6] = [
+        EV_REL::REL_X,
+        EV_REL::REL_Y,
+        EV_REL::REL_WHEEL,
+        EV_REL::REL_HWHEEL,
+        EV_REL::REL_WHEEL_HI_RES,
+        EV_REL::REL_HWHEEL_HI_RES,
+    ];
+
+    pub fn new(settings: &Touchpad) -> Self {
+        Self {
+            settings: settings.clone(),
+            last: [None; 2],
+            session: None,
+            tap: false,
+        }
+    }
+
+    /// Pointer input for fingers `touches` at `time`, while the pad is `clicked` or not. One
+    /// finger moves the pointer, two scroll, and a tap clicks the left or right button for one
+    /// report once the fingers are lifted.
+    pub fn update(
+        &mut self,
+        touches: &[Option<Touch>; 2],
+        clicked: bool,
+        time: Duration,
+    ) -> Pointer {
+        let mut pointer = Pointer::default();
+        let down: Vec<Touch> = touches.iter().flatten().copied().collect();
+        let deltas: Vec<(f32, f32)> = down
+            .iter()
+            .filter_map(|touch| {
+                let last = self.last.iter().flatten().find(|l| l.id == touch.id)?;
+                Some((
+                    touch.x as f32 - last.x as f32,
+                    touch.y as f32 - last.y as f32,
+                ))
+            })
+            .collect();
+        // fingers put down or lifted would make the pointer jump
+        let same_fingers =
+            deltas.len() == down.len() && self.last.iter().flatten().count() == down.len();
+        if same_fingers {
+            match deltas[..] {
+                [(dx, dy)] => {
+                    let gain = self.gain(dx.hypot(dy));
+                    pointer.motion = vec![(EV_REL::REL_X, dx * gain), (EV_REL::REL_Y, dy * gain)];
+                }
+                [(dx1, dy1), (dx2, dy2)] => {
+                    let speed = self.settings.scroll_speed;
+                    // fingers going up scroll up, which the wheel reports as positive
+                    let wheel = -(dy1 + dy2) / 2.0 * speed;
+                    let hwheel = (dx1 + dx2) / 2.0 * speed;
+                    pointer.motion = vec![
+                        (EV_REL::REL_WHEEL_HI_RES, wheel),
+                        (EV_REL::REL_WHEEL, wheel / WHEEL_NOTCH),
+                        (EV_REL::REL_HWHEEL_HI_RES, hwheel),
+                        (EV_REL::REL_HWHEEL, hwheel / WHEEL_NOTCH),
+                    ];
+                }
+                _ => {}
+            }
+        }
+
+        let started = self.session.is_none();
+        if let Some(session) = Session::track(&mut self.session, &down, time) {
+            let quick = session.duration(time) <= Duration::from_millis(self.settings.tap_time_ms);
+            if self.tap && quick {
+                match session.fingers {
+                    1 => pointer.buttons.push(EV_KEY::BTN_LEFT),
+                    2 => pointer.buttons.push(EV_KEY::BTN_RIGHT),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(session) = &self.session {
+            if started {
+                self.tap = true;
+            }
+            if clicked || session.moved() > self.settings.tap_distance as f32 {
+                self.tap = false;
+            }
+        }
+        self.last = *touches;
+        pointer
+    }
+
+    /// Pointer motion per touchpad unit at finger `speed`
+    fn gain(&self, speed: f32) -> f32 {
+        let fraction = (speed / ACCELERATION_SPEED).min(1.0);
+        if fraction == 0.0 {
+            return 0.0;
+        }
+        self.settings.curve.normalized(fraction) / fraction * self.settings.sensitivity
+    }
+}
+
+/// Deflection of the touchpad used as a stick, following the first finger put down
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stick {
+    settings: TouchStick,
+    /// Id of the finger the stick follows, and where it's centered
+    anchor: Option<(u8, f32, f32)>,
+}
+
+impl Stick {
+    pub fn new(settings: &TouchStick) -> Self {
+        Self {
+            settings: settings.clone(),
+            anchor: None,
+        }
+    }
+
+    /// Offset of the x & y axes from center for fingers `touches`, each `-1.0` to `1.0`. Lifting
+    /// the finger returns the stick to center.
+    pub fn update(&mut self, touches: &[Option<Touch>; 2]) -> (f32, f32) {
+        let followed = self
+            .anchor
+            .and_then(|(id, ..)| touches.iter().flatten().find(|touch| touch.id == id));
+        let touch = match followed {
+            Some(touch) => *touch,
+            None => {
+                self.anchor = None;
+                let Some(touch) = touches.iter().flatten().next() else {
+                    return (0.0, 0.0);
+                };
+                let (x, y) = match self.settings.origin {
+                    Origin::Touch => (touch.x as f32, touch.y as f32),
+                    Origin::Center => (TOUCHPAD_WIDTH as f32 / 2.0, TOUCHPAD_HEIGHT as f32 / 2.0),
+                };
+                self.anchor = Some((touch.id, x, y));
+                *touch
+            }
+        };
+        let (_, x, y) = self.anchor.expect("a followed finger has an anchor");
+        let radius = self.settings.radius as f32;
+        let (dx, dy) = ((touch.x as f32 - x) / radius, (touch.y as f32 - y) / radius);
+        let distance = dx.hypot(dy);
+        let deadzone = self.settings.deadzone;
+        if distance <= deadzone {
+            return (0.0, 0.0);
+        }
+        // the deflection starts from zero at the edge of the deadzone, and stops at the radius.
+        // Curving it rather than each axis keeps its direction.
+        let deflection = ((distance - deadzone) / (1.0 - deadzone)).min(1.0);
+        let scale = self.settings.curve.normalized(deflection) / distance;
+        (dx * scale, dy * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trackpad() -> Trackpad {
+        Trackpad::new(&Touchpad {
+            mode: Mode::Trackpad,
+            sensitivity: 1.0,
+            ..Touchpad::default()
+        })
+    }
+
+    fn finger(id: u8, x: u16, y: u16) -> Option<Touch> {
+        Some(Touch { id, x, y })
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn motion(pointer: &Pointer, rel: EV_REL) -> f32 {
+        pointer
+            .motion
+            .iter()
+            .find(|(r, _)| *r == rel)
+            .map_or(0.0, |(_, motion)| *motion)
+    }
+
+    #[test]
+    fn layouts_cover_the_pad_in_reading_order() {
+        let rects = Regions::Layout(Layout::Grid3x2).rects();
+        assert_eq!(rects.len(), 6);
+        assert_eq!(rects[1], [1.0 / 3.0, 0.0, 2.0 / 3.0, 0.5]);
+        let corner = Touch {
+            id: 0,
+            x: TOUCHPAD_WIDTH - 1,
+            y: TOUCHPAD_HEIGHT - 1,
+        };
+        assert!(contains(&rects[5], &corner));
+        assert!(!contains(&rects[4], &corner));
+
+        let regions: Touchpad = toml::from_str("regions = [[0.0, 0.0, 0.25, 1.0]]").unwrap();
+        assert_eq!(regions.regions.rects(), [[0.0, 0.0, 0.25, 1.0]]);
+        let halves: Touchpad = toml::from_str("regions = \"halves\"").unwrap();
+        assert_eq!(halves.regions.rects()[1], [0.5, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn stick_follows_the_first_finger_from_where_it_touched() {
+        let mut stick = Stick::new(&TouchStick {
+            deadzone: 0.0,
+            ..TouchStick::default()
+        });
+        assert_eq!(stick.update(&[finger(4, 900, 400), None]), (0.0, 0.0));
+        let (x, y) = stick.update(&[finger(4, 1050, 400), finger(5, 100, 100)]);
+        assert!((x - 0.5).abs() < 0.01 && y == 0.0);
+        let (x, y) = stick.update(&[finger(4, 900, 0), finger(5, 100, 100)]);
+        assert!(
+            x == 0.0 && (y + 1.0).abs() < 0.01,
+            "the radius is full deflection"
+        );
+        assert_eq!(stick.update(&[None, finger(5, 100, 100)]), (0.0, 0.0));
+        assert_eq!(stick.update(&[None, None]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn stick_can_center_on_the_pad_with_a_deadzone() {
+        let mut stick = Stick::new(&TouchStick {
+            origin: Origin::Center,
+            deadzone: 0.5,
+            ..TouchStick::default()
+        });
+        assert_eq!(stick.update(&[finger(1, 1060, 471), None]), (0.0, 0.0));
+        let (x, _) = stick.update(&[finger(1, 960 + 225, 471), None]);
+        assert!((x - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn one_finger_moves_the_pointer() {
+        let mut trackpad = trackpad();
+        let down = trackpad.update(&[finger(1, 500, 400), None], false, ms(0));
+        assert_eq!(
+            down,
+            Pointer::default(),
+            "putting a finger down doesn't move"
+        );
+        let moved = trackpad.update(&[finger(1, 510, 394), None], false, ms(4));
+        assert!((motion(&moved, EV_REL::REL_X) - 10.0).abs() < 0.1);
+        assert!((motion(&moved, EV_REL::REL_Y) + 6.0).abs() < 0.1);
+
+        // a new finger starts where it's put down
+        trackpad.update(&[None, None], false, ms(8));
+        let down = trackpad.update(&[finger(2, 1500, 100), None], false, ms(12));
+        assert!(down.motion.is_empty());
+    }
+
+    #[test]
+    fn acceleration_curve_slows_slow_motion() {
+        let mut trackpad = Trackpad::new(&Touchpad {
+            mode: Mode::Trackpad,
+            sensitivity: 1.0,
+            curve: Curve::InQuad,
+            ..Touchpad::default()
+        });
+        trackpad.update(&[finger(1, 500, 400), None], false, ms(0));
+        let slow = trackpad.update(&[finger(1, 504, 400), None], false, ms(4));
+        assert!(motion(&slow, EV_REL::REL_X) < 1.0);
+        let fast = trackpad.update(&[finger(1, 544, 400), None], false, ms(8));
+        assert!((motion(&fast, EV_REL::REL_X) - 40.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn linear_curve_keeps_slow_motion() {
+        let trackpad = trackpad();
+        for speed in [1.0, 2.0, 3.0] {
+            let gain = trackpad.gain(speed);
+            assert!((gain - 1.0).abs() < 1e-5, "{} at {}", gain, speed);
+        }
+    }
+
+    #[test]
+    fn two_fingers_scroll() {
+        let mut trackpad = trackpad();
+        trackpad.update(&[finger(1, 500, 400), finger(2, 700, 400)], false, ms(0));
+        let scrolled = trackpad.update(&[finger(1, 500, 340), finger(2, 700, 340)], false, ms(4));
+        assert_eq!(motion(&scrolled, EV_REL::REL_WHEEL_HI_RES), 120.0);
+        assert_eq!(motion(&scrolled, EV_REL::REL_WHEEL), 1.0);
+        assert_eq!(motion(&scrolled, EV_REL::REL_HWHEEL_HI_RES), 0.0);
+        assert_eq!(motion(&scrolled, EV_REL::REL_X), 0.0);
+    }
+
+    #[test]
+    fn taps_click_for_one_report() {
+        let mut trackpad = trackpad();
+        trackpad.update(&[finger(1, 500, 400), None], false, ms(0));
+        trackpad.update(&[finger(1, 505, 400), None], false, ms(50));
+        let lifted = trackpad.update(&[None, None], false, ms(100));
+        assert_eq!(lifted.buttons, [EV_KEY::BTN_LEFT]);
+        assert!(trackpad
+            .update(&[None, None], false, ms(104))
+            .buttons
+            .is_empty());
+
+        trackpad.update(&[finger(2, 500, 400), None], false, ms(200));
+        trackpad.update(&[finger(2, 500, 400), finger(3, 800, 400)], false, ms(220));
+        let lifted = trackpad.update(&[None, None], false, ms(300));
+        assert_eq!(lifted.buttons, [EV_KEY::BTN_RIGHT]);
+    }
+
+    #[test]
+    fn long_moving_or_clicking_touches_are_no_taps() {
+        let mut trackpad = trackpad();
+        trackpad.update(&[finger(1, 500, 400), None], false, ms(0));
+        assert!(trackpad
+            .update(&[None, None], false, ms(400))
+            .buttons
+            .is_empty());
+
+        trackpad.update(&[finger(2, 500, 400), None], false, ms(500));
+        trackpad.update(&[finger(2, 700, 400), None], false, ms(550));
+        assert!(trackpad
+            .update(&[None, None], false, ms(600))
+            .buttons
+            .is_empty());
+
+        trackpad.update(&[finger(3, 500, 400), None], true, ms(700));
+        assert!(trackpad
+            .update(&[None, None], false, ms(750))
+            .buttons
+            .is_empty());
+    }
+
+    #[test]
+    fn session_lasts_until_the_last_finger_is_lifted() {
+        let a = Touch {
+            id: 1,
+            x: 500,
+            y: 400,
+        };
+        let b = Touch {
+            id: 2,
+            x: 800,
+            y: 400,
+        };
+        let mut session = None;
+        assert_eq!(Session::track(&mut session, &[a], ms(0)), None);
+        assert_eq!(Session::track(&mut session, &[a, b], ms(20)), None);
+        let moved = Touch {
+            x: 530,
+            y: 440,
+            ..a
+        };
+        assert_eq!(Session::track(&mut session, &[moved], ms(40)), None);
+
+        let ended = Session::track(&mut session, &[], ms(60)).unwrap();
+        assert_eq!(session, None);
+        assert_eq!(ended.fingers, 2);
+        assert_eq!(ended.paths, [(a, moved), (b, b)]);
+        assert_eq!(ended.duration(ms(60)), ms(60));
+        assert_eq!(ended.moved(), 50.0);
+    }
+}
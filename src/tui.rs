@@ -0,0 +1,396 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    event::{KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use ds4linux::{
+    curve::Curve,
+    hid::{DS4State, ANALOG_MAX},
+    profile::{Axis, Profile},
+};
+
+const PLOT_WIDTH: usize = 64;
+const PLOT_HEIGHT: usize = 24;
+// control points a preset curve is split into once it gets edited
+const EDIT_POINTS: usize = 5;
+const POINT_STEP: f32 = 0.01;
+
+/// Puts the terminal in raw mode on an alternate screen, restoring it when dropped.
+pub struct Terminal;
+
+impl Terminal {
+    pub fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        queue!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = queue!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = io::stdout().flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AxisId {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl AxisId {
+    const ALL: [AxisId; 4] = [AxisId::LeftX, AxisId::LeftY, AxisId::RightX, AxisId::RightY];
+
+    fn name(&self) -> &'static str {
+        match self {
+            AxisId::LeftX => "left stick x",
+            AxisId::LeftY => "left stick y",
+            AxisId::RightX => "right stick x",
+            AxisId::RightY => "right stick y",
+        }
+    }
+
+    fn axis<'a>(&self, profile: &'a Profile) -> &'a Axis {
+        match self {
+            AxisId::LeftX => &profile.left_stick.x,
+            AxisId::LeftY => &profile.left_stick.y,
+            AxisId::RightX => &profile.right_stick.x,
+            AxisId::RightY => &profile.right_stick.y,
+        }
+    }
+
+    fn axis_mut<'a>(&self, profile: &'a mut Profile) -> &'a mut Axis {
+        match self {
+            AxisId::LeftX => &mut profile.left_stick.x,
+            AxisId::LeftY => &mut profile.left_stick.y,
+            AxisId::RightX => &mut profile.right_stick.x,
+            AxisId::RightY => &mut profile.right_stick.y,
+        }
+    }
+
+    fn raw(&self, state: &DS4State) -> u8 {
+        match self {
+            AxisId::LeftX => state.lsx,
+            AxisId::LeftY => state.lsy,
+            AxisId::RightX => state.rsx,
+            AxisId::RightY => state.rsy,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    /// Profile was edited and should be applied to the virtual device
+    Changed,
+    Save,
+    Quit,
+}
+
+/// Interactive curve editor, drawing the response curve of one stick axis with its live position
+/// and letting control points & deadzone be moved with the keyboard.
+pub struct Editor {
+    axis: AxisId,
+    point: usize,
+    status: String,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            axis: AxisId::LeftX,
+            point: 0,
+            status: String::new(),
+        }
+    }
+
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = status.into();
+    }
+
+    pub fn handle(&mut self, key: KeyEvent, profile: &mut Profile) -> Action {
+        let step = if key.modifiers.contains(KeyModifiers::SHIFT) {
+            POINT_STEP * 5.0
+        } else {
+            POINT_STEP
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('s') => Action::Save,
+            KeyCode::Tab | KeyCode::BackTab => {
+                let current = AxisId::ALL.iter().position(|a| *a == self.axis).unwrap();
+                let next = if key.code == KeyCode::Tab {
+                    current + 1
+                } else {
+                    current + AxisId::ALL.len() - 1
+                };
+                self.axis = AxisId::ALL[next % AxisId::ALL.len()];
+                self.point = 0;
+                Action::None
+            }
+            KeyCode::Left => {
+                self.point = self.point.saturating_sub(1);
+                Action::None
+            }
+            KeyCode::Right => {
+                let len = self.axis.axis(profile).curve.to_points(EDIT_POINTS).len();
+                self.point = usize::min(self.point + 1, len.saturating_sub(1));
+                Action::None
+            }
+            KeyCode::Up => self.move_point(profile, step),
+            KeyCode::Down => self.move_point(profile, -step),
+            KeyCode::Char('+') | KeyCode::Char(']') => {
+                let axis = self.axis.axis_mut(profile);
+                axis.deadzone = u8::min(axis.deadzone + 1, ANALOG_MAX / 2);
+                Action::Changed
+            }
+            KeyCode::Char('-') | KeyCode::Char('[') => {
+                let axis = self.axis.axis_mut(profile);
+                axis.deadzone = axis.deadzone.saturating_sub(1);
+                Action::Changed
+            }
+            KeyCode::Char('r') => {
+                self.axis.axis_mut(profile).curve = Curve::Linear;
+                Action::Changed
+            }
+            _ => Action::None,
+        }
+    }
+
+    /// Move selected control point's output by `delta`, keeping the curve monotone
+    fn move_point(&mut self, profile: &mut Profile, delta: f32) -> Action {
+        let axis = self.axis.axis_mut(profile);
+        let mut points = axis.curve.to_points(EDIT_POINTS);
+        if points.is_empty() {
+            return Action::None;
+        }
+        self.point = usize::min(self.point, points.len() - 1);
+        let lower = if self.point == 0 {
+            0.0
+        } else {
+            points[self.point - 1][1]
+        };
+        let upper = points.get(self.point + 1).map_or(1.0, |p| p[1]);
+        let output = &mut points[self.point][1];
+        *output = ((*output + delta).clamp(lower, upper) * 100.0).round() / 100.0;
+        axis.curve = Curve::Points(points);
+        Action::Changed
+    }
+
+    pub fn draw(
+        &self,
+        out: &mut impl Write,
+        profile: &Profile,
+        state: &DS4State,
+    ) -> io::Result<()> {
+        let axis = self.axis.axis(profile);
+        let mut grid = vec![vec![' '; PLOT_WIDTH]; PLOT_HEIGHT];
+        let to_col = |x: f32| ((x / ANALOG_MAX as f32) * (PLOT_WIDTH - 1) as f32).round() as usize;
+        let to_row = |y: f32| {
+            let y = y.clamp(0.0, ANALOG_MAX as f32);
+            PLOT_HEIGHT - 1 - ((y / ANALOG_MAX as f32) * (PLOT_HEIGHT - 1) as f32).round() as usize
+        };
+
+        // deadzone boundaries
+        if axis.deadzone > 0 {
+            for x in [128 - axis.deadzone as i16, 128 + axis.deadzone as i16] {
+                let col = to_col(x.clamp(0, ANALOG_MAX as i16) as f32);
+                for row in grid.iter_mut() {
+                    row[col] = ':';
+                }
+            }
+        }
+        let curve_rows: Vec<usize> = (0..PLOT_WIDTH)
+            .map(|col| {
+                let x = (col as f32 / (PLOT_WIDTH - 1) as f32 * ANALOG_MAX as f32).round() as u8;
+                to_row(axis.apply(x) as f32)
+            })
+            .collect();
+        for (col, row) in curve_rows.into_iter().enumerate() {
+            grid[row][col] = '.';
+        }
+        let middle = ANALOG_MAX as f32 / 2.0;
+        for (i, point) in axis.curve.to_points(EDIT_POINTS).iter().enumerate() {
+            let marker = if i == self.point { '@' } else { 'o' };
+            for sign in [1.0, -1.0] {
+                let col = to_col(middle + sign * point[0] * middle);
+                grid[to_row(middle + sign * point[1] * middle)][col] = marker;
+            }
+        }
+        let raw = self.axis.raw(state);
+        let output = axis.apply(raw);
+        grid[to_row(output as f32)][to_col(raw as f32)] = '#';
+
+        queue!(out, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All))?;
+        let curve = match &axis.curve {
+            Curve::Points(_) => "points".to_string(),
+            Curve::Bezier(_) => "bezier".to_string(),
+            preset => format!("{:?}", preset).to_lowercase(),
+        };
+        let header = format!(
+            "{}  curve: {}  deadzone: {}  input: {:3}  output: {:3}",
+            self.axis.name(),
+            curve,
+            axis.deadzone,
+            raw,
+            output
+        );
+        queue!(out, Print(header), cursor::MoveToNextLine(1))?;
+        queue!(
+            out,
+            Print(format!("+{}+", "-".repeat(PLOT_WIDTH))),
+            cursor::MoveToNextLine(1)
+        )?;
+        for row in grid {
+            let line: String = row.into_iter().collect();
+            queue!(out, Print(format!("|{}|", line)), cursor::MoveToNextLine(1))?;
+        }
+        queue!(
+            out,
+            Print(format!("+{}+", "-".repeat(PLOT_WIDTH))),
+            cursor::MoveToNextLine(1)
+        )?;
+        queue!(
+            out,
+            Print("tab: next axis  left/right: select point  up/down: move point (shift: faster)"),
+            cursor::MoveToNextLine(1),
+            Print("[/]: deadzone  r: reset to linear  s: save  q: quit"),
+            cursor::MoveToNextLine(1),
+            Print(&self.status)
+        )?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    /// Profile with linear curves, which are split into evenly spaced points
+    fn linear() -> Profile {
+        let mut profile = Profile::default();
+        for axis in AxisId::ALL {
+            axis.axis_mut(&mut profile).curve = Curve::Linear;
+        }
+        profile
+    }
+
+    /// Output of every control point of the left stick's x axis
+    fn outputs(profile: &Profile) -> Vec<f32> {
+        match &profile.left_stick.x.curve {
+            Curve::Points(points) => points.iter().map(|p| p[1]).collect(),
+            curve => panic!("expected control points, but was {:?}", curve),
+        }
+    }
+
+    #[test]
+    fn keys_quit_save_and_edit_the_deadzone() {
+        let mut editor = Editor::new();
+        let mut profile = Profile::default();
+        assert_eq!(
+            editor.handle(key(KeyCode::Char('q')), &mut profile),
+            Action::Quit
+        );
+        assert_eq!(editor.handle(key(KeyCode::Esc), &mut profile), Action::Quit);
+        assert_eq!(
+            editor.handle(key(KeyCode::Char('s')), &mut profile),
+            Action::Save
+        );
+        assert_eq!(
+            editor.handle(key(KeyCode::Char('x')), &mut profile),
+            Action::None
+        );
+        assert_eq!(profile, Profile::default());
+
+        assert_eq!(
+            editor.handle(key(KeyCode::Char(']')), &mut profile),
+            Action::Changed
+        );
+        assert_eq!(
+            editor.handle(key(KeyCode::Char('+')), &mut profile),
+            Action::Changed
+        );
+        assert_eq!(profile.left_stick.x.deadzone, 2);
+        editor.handle(key(KeyCode::Char('-')), &mut profile);
+        assert_eq!(profile.left_stick.x.deadzone, 1);
+        // the deadzone doesn't go below zero
+        editor.handle(key(KeyCode::Char('[')), &mut profile);
+        editor.handle(key(KeyCode::Char('[')), &mut profile);
+        assert_eq!(profile.left_stick.x.deadzone, 0);
+    }
+
+    #[test]
+    fn points_are_selected_and_moved_in_order() {
+        let mut editor = Editor::new();
+        let mut profile = linear();
+        // selecting stops at either end of the curve
+        assert_eq!(
+            editor.handle(key(KeyCode::Left), &mut profile),
+            Action::None
+        );
+        for _ in 0..EDIT_POINTS + 2 {
+            editor.handle(key(KeyCode::Right), &mut profile);
+        }
+        assert_eq!(
+            editor.handle(key(KeyCode::Down), &mut profile),
+            Action::Changed
+        );
+        assert_eq!(outputs(&profile), [0.0, 0.25, 0.5, 0.75, 0.99]);
+
+        editor.handle(key(KeyCode::Left), &mut profile);
+        editor.handle(key(KeyCode::Left), &mut profile);
+        editor.handle(key(KeyCode::Up), &mut profile);
+        editor.handle(
+            KeyEvent::new(KeyCode::Up, KeyModifiers::SHIFT),
+            &mut profile,
+        );
+        assert_eq!(outputs(&profile), [0.0, 0.25, 0.56, 0.75, 0.99]);
+
+        // a point doesn't move past its neighbours
+        for _ in 0..10 {
+            editor.handle(
+                KeyEvent::new(KeyCode::Down, KeyModifiers::SHIFT),
+                &mut profile,
+            );
+        }
+        assert_eq!(outputs(&profile), [0.0, 0.25, 0.25, 0.75, 0.99]);
+
+        assert_eq!(
+            editor.handle(key(KeyCode::Char('r')), &mut profile),
+            Action::Changed
+        );
+        assert_eq!(profile.left_stick.x.curve, Curve::Linear);
+    }
+
+    #[test]
+    fn tab_switches_axes_and_selects_the_first_point() {
+        let mut editor = Editor::new();
+        let mut profile = linear();
+        editor.handle(key(KeyCode::Right), &mut profile);
+        assert_eq!(editor.handle(key(KeyCode::Tab), &mut profile), Action::None);
+        editor.handle(key(KeyCode::Up), &mut profile);
+        assert_eq!(profile.left_stick.x.curve, Curve::Linear);
+        let Curve::Points(points) = &profile.left_stick.y.curve else {
+            panic!("left stick y wasn't edited");
+        };
+        assert_eq!(points[0][1], 0.01);
+
+        // going back from the first axis wraps around to the last
+        editor.handle(key(KeyCode::BackTab), &mut profile);
+        editor.handle(key(KeyCode::BackTab), &mut profile);
+        editor.handle(key(KeyCode::Char(']')), &mut profile);
+        assert_eq!(profile.right_stick.y.deadzone, 1);
+    }
+}
@@ -0,0 +1,772 @@
+use std::fmt::Display;
+
+use toml_edit::ImDocument;
+
+use crate::{
+    config::Config,
+    curve::Curve,
+    inherit,
+    mapping::{self, Input, Output},
+    preset,
+    profile::{Axis, Profile},
+};
+
+/// A problem found in a config file, at 1-based `line` & `column`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Problem found in an already parsed config, at the table keys leading to the offending value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub key: Vec<String>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(key: &[&str], message: impl Into<String>) -> Self {
+        Self {
+            key: key.iter().map(|k| k.to_string()).collect(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Parse and validate config `source`, returning every problem found in it when it's invalid.
+/// Profiles of the returned config are resolved, holding the settings they inherit.
+pub fn check(source: &str) -> Result<Config, Vec<Diagnostic>> {
+    // the file as written has the spans to point at mistakes, which resolving loses
+    toml::from_str::<Config>(source).map_err(|e| {
+        let offset = e.span().map_or(0, |span| span.start);
+        vec![diagnostic(source, offset, e.message().trim_end())]
+    })?;
+    let mut table: toml::Table = toml::from_str(source).expect("config is valid toml");
+    preset::apply(&mut table);
+    if let Err(problems) = inherit::resolve(&mut table) {
+        return Err(locate_problems(source, problems));
+    }
+    let config: Config = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| vec![diagnostic(source, 0, e.message().trim_end())])?;
+    let problems = validate(&config);
+    if problems.is_empty() {
+        return Ok(config);
+    }
+    Err(locate_problems(source, problems))
+}
+
+fn locate_problems(source: &str, problems: Vec<Problem>) -> Vec<Diagnostic> {
+    // the source parsed into a config, so it's valid toml
+    let document = ImDocument::parse(source).expect("config is valid toml");
+    problems
+        .into_iter()
+        .map(|problem| {
+            let offset = locate(&document, &problem.key);
+            diagnostic(
+                source,
+                offset,
+                format!("{}: {}", problem.key.join("."), problem.message),
+            )
+        })
+        .collect()
+}
+
+/// Find the values of `config` which are well formed, but can't be used as is.
+pub fn validate(config: &Config) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    if config.profiles.is_empty() {
+        problems.push(Problem::new(
+            &["profiles"],
+            "at least one profile is needed",
+        ));
+    }
+    if let Some(name) = &config.default_profile {
+        if !config.profiles.contains_key(name) {
+            problems.push(Problem::new(
+                &["default_profile"],
+                format!("there is no profile named `{}`", name),
+            ));
+        }
+    }
+    let chord = &config.switch;
+    let chord_buttons = [
+        ("hold", chord.hold),
+        ("next", chord.next),
+        ("previous", chord.previous),
+    ];
+    find_conflicts(&["switch"], &chord_buttons, &mut problems);
+
+    if config.auto_switch.interval_ms == 0 {
+        problems.push(Problem::new(
+            &["auto_switch", "interval_ms"],
+            "must be at least 1",
+        ));
+    }
+    if config
+        .auto_switch
+        .hook
+        .as_deref()
+        .is_some_and(|hook| hook.trim().is_empty())
+    {
+        problems.push(Problem::new(&["auto_switch", "hook"], "command is empty"));
+    }
+
+    for (name, profile) in &config.profiles {
+        validate_profile(&["profiles", name], profile, &mut problems);
+    }
+
+    problems
+}
+
+fn validate_profile(key: &[&str], profile: &Profile, problems: &mut Vec<Problem>) {
+    for (stick_name, stick) in [
+        ("left_stick", &profile.left_stick),
+        ("right_stick", &profile.right_stick),
+    ] {
+        if !(0..=127).contains(&stick.flat) {
+            problems.push(Problem::new(
+                &[key, &[stick_name, "flat"]].concat(),
+                format!("{} is out of range, expected 0 to 127", stick.flat),
+            ));
+        }
+        for (axis_name, axis) in [("x", &stick.x), ("y", &stick.y)] {
+            validate_axis(&[key, &[stick_name, axis_name]].concat(), axis, problems);
+        }
+    }
+
+    let touchpad = &profile.touchpad;
+    if !(touchpad.sensitivity > 0.0 && touchpad.sensitivity.is_finite()) {
+        problems.push(Problem::new(
+            &[key, &["touchpad", "sensitivity"]].concat(),
+            format!(
+                "{} is out of range, expected more than 0.0",
+                touchpad.sensitivity
+            ),
+        ));
+    }
+    if !touchpad.scroll_speed.is_finite() {
+        problems.push(Problem::new(
+            &[key, &["touchpad", "scroll_speed"]].concat(),
+            format!("{} is not a number", touchpad.scroll_speed),
+        ));
+    }
+    validate_curve(
+        &[key, &["touchpad", "curve"]].concat(),
+        &touchpad.curve,
+        problems,
+    );
+    let stick = &touchpad.stick;
+    if !(0.0..1.0).contains(&stick.deadzone) {
+        problems.push(Problem::new(
+            &[key, &["touchpad", "stick", "deadzone"]].concat(),
+            format!("{} is out of range, expected 0.0 up to 1.0", stick.deadzone),
+        ));
+    }
+    if stick.radius == 0 {
+        problems.push(Problem::new(
+            &[key, &["touchpad", "stick", "radius"]].concat(),
+            "must be at least 1",
+        ));
+    }
+    find_conflicts(
+        &[key, &["touchpad", "stick"]].concat(),
+        &[("x", stick.x), ("y", stick.y)],
+        problems,
+    );
+    validate_curve(
+        &[key, &["touchpad", "stick", "curve"]].concat(),
+        &stick.curve,
+        problems,
+    );
+
+    let gestures = &touchpad.gestures;
+    if !(0.0..=0.5).contains(&gestures.edge_width) {
+        problems.push(Problem::new(
+            &[key, &["touchpad", "gestures", "edge_width"]].concat(),
+            format!(
+                "{} is out of range, expected 0.0 to 0.5",
+                gestures.edge_width
+            ),
+        ));
+    }
+    for (name, distance) in [
+        ("swipe_distance", gestures.swipe_distance),
+        ("pinch_distance", gestures.pinch_distance),
+    ] {
+        if distance == 0 {
+            problems.push(Problem::new(
+                &[key, &["touchpad", "gestures", name]].concat(),
+                "must be at least 1",
+            ));
+        }
+    }
+    let regions = touchpad.regions.rects();
+    for (i, [left, top, right, bottom]) in regions.iter().enumerate() {
+        let inside = [left, top, right, bottom]
+            .iter()
+            .all(|value| (0.0..=1.0).contains(*value));
+        if !inside || left >= right || top >= bottom {
+            problems.push(Problem::new(
+                &[key, &["touchpad", "regions"]].concat(),
+                format!(
+                    "region {} is no rectangle within the pad, expected [left, top, right, bottom] from 0.0 to 1.0",
+                    i + 1
+                ),
+            ));
+        }
+    }
+
+    let gyro = &profile.gyro;
+    for (name, value) in [
+        ("sensitivity_x", gyro.sensitivity_x),
+        ("sensitivity_y", gyro.sensitivity_y),
+        ("mouse_speed", gyro.mouse_speed),
+    ] {
+        if !value.is_finite() {
+            problems.push(Problem::new(
+                &[key, &["gyro", name]].concat(),
+                format!("{} is not a number", value),
+            ));
+        }
+    }
+    validate_curve(&[key, &["gyro", "curve"]].concat(), &gyro.curve, problems);
+    if !(gyro.stick_speed > 0.0 && gyro.stick_speed.is_finite()) {
+        problems.push(Problem::new(
+            &[key, &["gyro", "stick_speed"]].concat(),
+            format!(
+                "{} is out of range, expected more than 0.0",
+                gyro.stick_speed
+            ),
+        ));
+    }
+    for (name, threshold) in [
+        ("smoothing_threshold", gyro.smoothing_threshold),
+        ("tightening_threshold", gyro.tightening_threshold),
+    ] {
+        if !(threshold >= 0.0 && threshold.is_finite()) {
+            problems.push(Problem::new(
+                &[key, &["gyro", name]].concat(),
+                format!("{} is out of range, expected 0.0 or more", threshold),
+            ));
+        }
+    }
+
+    let flick = &profile.flick_stick;
+    if !(flick.counts_per_360 > 0.0 && flick.counts_per_360.is_finite()) {
+        problems.push(Problem::new(
+            &[key, &["flick_stick", "counts_per_360"]].concat(),
+            format!(
+                "{} is out of range, expected more than 0.0",
+                flick.counts_per_360
+            ),
+        ));
+    }
+    if !(flick.threshold > 0.0 && flick.threshold <= 1.0) {
+        problems.push(Problem::new(
+            &[key, &["flick_stick", "threshold"]].concat(),
+            format!(
+                "{} is out of range, expected more than 0.0 up to 1.0",
+                flick.threshold
+            ),
+        ));
+    }
+
+    let tilt = &profile.tilt;
+    if !(tilt.deadzone >= 0.0 && tilt.deadzone < tilt.max_angle && tilt.max_angle <= 90.0) {
+        problems.push(Problem::new(
+            &[key, &["tilt"]].concat(),
+            format!(
+                "deadzone {} and max_angle {} are out of range, expected 0.0 <= deadzone < max_angle <= 90.0",
+                tilt.deadzone, tilt.max_angle
+            ),
+        ));
+    }
+    validate_curve(&[key, &["tilt", "curve"]].concat(), &tilt.curve, problems);
+
+    let movements = &profile.movements;
+    for (name, threshold) in [
+        ("shake_threshold", movements.shake_threshold),
+        ("tap_threshold", movements.tap_threshold),
+    ] {
+        if !(threshold > 0.0 && threshold.is_finite()) {
+            problems.push(Problem::new(
+                &[key, &["movements", name]].concat(),
+                format!("{} is out of range, expected more than 0.0", threshold),
+            ));
+        }
+    }
+
+    let buttons = &profile.buttons;
+    let keys = [
+        ("square", buttons.square),
+        ("cross", buttons.cross),
+        ("circle", buttons.circle),
+        ("triangle", buttons.triangle),
+        ("l1", buttons.l1),
+        ("l2", buttons.l2),
+        ("l3", buttons.l3),
+        ("r1", buttons.r1),
+        ("r2", buttons.r2),
+        ("r3", buttons.r3),
+        ("select", buttons.select),
+        ("start", buttons.start),
+        ("ps", buttons.ps),
+        ("touchpad", buttons.touchpad),
+    ];
+    find_conflicts(&[key, &["buttons"]].concat(), &keys, problems);
+
+    let axes = &profile.axes;
+    let abs = [
+        ("dpad_x", axes.dpad_x),
+        ("dpad_y", axes.dpad_y),
+        ("left_x", axes.left_x),
+        ("left_y", axes.left_y),
+        ("right_x", axes.right_x),
+        ("right_y", axes.right_y),
+    ];
+    find_conflicts(&[key, &["axes"]].concat(), &abs, problems);
+
+    // bindings of the map replace those of the tables for the same inputs, and are checked
+    // against what's left of them
+    let mut bound: Vec<(String, Input, Output)> = mapping::table_bindings(profile)
+        .into_iter()
+        .filter(|(input, _)| !profile.map.contains_key(input))
+        .map(|(input, binding)| (input.to_string(), input, binding.output))
+        .collect();
+    for (input, binding) in &profile.map {
+        let output = &binding.output;
+        let name = input.to_string();
+        if let Some((other, ..)) = bound
+            .iter()
+            .find(|(_, other, o)| o == output && conflicts(output, input, other))
+        {
+            problems.push(Problem::new(
+                &[key, &["map", &name]].concat(),
+                format!("{} is already bound to `{}`", output, other),
+            ));
+        }
+        bound.push((format!("map.{}", name), *input, output.clone()));
+    }
+
+    for (input, binding) in &profile.map {
+        if let Input::Region(number, _) = input {
+            if *number as usize > regions.len() {
+                problems.push(Problem::new(
+                    &[key, &["map", &input.to_string()]].concat(),
+                    format!("there is no touchpad region {}", number),
+                ));
+            }
+        }
+        let input = input.to_string();
+        let key = [key, &["map", input.as_str()]].concat();
+        if !(binding.threshold > 0.0 && binding.threshold <= 1.0) {
+            problems.push(Problem::new(
+                &[&key[..], &["threshold"]].concat(),
+                format!(
+                    "{} is out of range, expected more than 0.0 up to 1.0",
+                    binding.threshold
+                ),
+            ));
+        }
+        if !binding.speed.is_finite() {
+            problems.push(Problem::new(
+                &[&key[..], &["speed"]].concat(),
+                format!("{} is not a number", binding.speed),
+            ));
+        }
+        validate_curve(&[&key[..], &["curve"]].concat(), &binding.curve, problems);
+    }
+}
+
+fn validate_axis(key: &[&str], axis: &Axis, problems: &mut Vec<Problem>) {
+    if axis.deadzone > 127 {
+        problems.push(Problem::new(
+            &[key, &["deadzone"]].concat(),
+            format!("{} is out of range, expected 0 to 127", axis.deadzone),
+        ));
+    }
+    if !(0.0..=1.0).contains(&axis.anti_deadzone) {
+        problems.push(Problem::new(
+            &[key, &["anti_deadzone"]].concat(),
+            format!(
+                "{} is out of range, expected 0.0 to 1.0",
+                axis.anti_deadzone
+            ),
+        ));
+    }
+    if !(axis.sensitivity > 0.0 && axis.sensitivity.is_finite()) {
+        problems.push(Problem::new(
+            &[key, &["sensitivity"]].concat(),
+            format!(
+                "{} is out of range, expected more than 0.0",
+                axis.sensitivity
+            ),
+        ));
+    }
+    validate_curve(&[key, &["curve"]].concat(), &axis.curve, problems);
+}
+
+fn validate_curve(key: &[&str], curve: &Curve, problems: &mut Vec<Problem>) {
+    if let Curve::Bezier([x1, _, x2, _]) = *curve {
+        if !(0.0..=1.0).contains(&x1) || !(0.0..=1.0).contains(&x2) {
+            problems.push(Problem::new(
+                key,
+                "control point inputs must be within 0.0 and 1.0",
+            ));
+        }
+    }
+    if let Curve::Points(points) = curve {
+        if points
+            .iter()
+            .flatten()
+            .any(|value| !(0.0..=1.0).contains(value))
+        {
+            problems.push(Problem::new(
+                key,
+                "control points must be within 0.0 and 1.0",
+            ));
+        }
+        let mut prev = [0.0, 0.0];
+        for (i, point) in points.iter().enumerate() {
+            // the implicit first point at [0, 0] may be given explicitly
+            let first_at_origin = i == 0 && point[0] == 0.0;
+            if (point[0] <= prev[0] && !first_at_origin) || point[1] < prev[1] {
+                problems.push(Problem::new(
+                    key,
+                    format!(
+                        "curve is not monotone at point {} {:?}, inputs must increase and outputs must not decrease",
+                        i + 1,
+                        point
+                    ),
+                ));
+                break;
+            }
+            prev = *point;
+        }
+    }
+}
+
+/// Whether inputs `a` & `b` can't share `output`: a key is pressed by one button, and an axis
+/// follows one stick or trigger. Regions, gestures & such add to them.
+fn conflicts(output: &Output, a: &Input, b: &Input) -> bool {
+    let button = |input: &Input| matches!(input, Input::Button(_));
+    let analog = |input: &Input| matches!(input, Input::Analog(_) | Input::Half(..));
+    match output {
+        Output::Key(_) => button(a) && button(b),
+        Output::Abs(_) => analog(a) && analog(b),
+        Output::None | Output::Combo(_) | Output::Rel(_) => false,
+    }
+}
+
+/// Report codes bound to more than one input
+fn find_conflicts<T: PartialEq + std::fmt::Debug>(
+    key: &[&str],
+    bindings: &[(&str, T)],
+    problems: &mut Vec<Problem>,
+) {
+    for (i, (name, code)) in bindings.iter().enumerate() {
+        if let Some((other, _)) = bindings[..i].iter().find(|(_, c)| c == code) {
+            problems.push(Problem::new(
+                &[key, &[name]].concat(),
+                format!("{:?} is already bound to `{}`", code, other),
+            ));
+        }
+    }
+}
+
+fn diagnostic(source: &str, offset: usize, message: impl Into<String>) -> Diagnostic {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Diagnostic {
+        line,
+        column: before[line_start..].chars().count() + 1,
+        message: message.into(),
+    }
+}
+
+/// Offset of the value at `key`, or of its closest parent present in the document. Values left
+/// out of the file are reported where they would have been set.
+fn locate(document: &ImDocument<&str>, key: &[String]) -> usize {
+    let mut item = document.as_item();
+    let mut offset = 0;
+    for part in key {
+        match item
+            .as_table_like()
+            .and_then(|table| table.get_key_value(part))
+        {
+            Some((key, child)) => {
+                // implicit tables, like `profile` in `[profiles.default.buttons]`, only have a span on their key
+                if let Some(span) = child.span().or_else(|| key.span()) {
+                    offset = span.start;
+                }
+                item = child;
+            }
+            None => break,
+        }
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<Diagnostic> {
+        check(source).unwrap_err()
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        assert!(check(include_str!("../config.example.toml")).is_ok());
+    }
+
+    #[test]
+    fn unknown_button_name_is_located() {
+        let errors =
+            errors("[profiles.default.buttons]\ncross = \"BTN_SOUTH\"\nsqaure = \"BTN_WEST\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (3, 1));
+        assert!(errors[0].message.contains("unknown field `sqaure`"));
+    }
+
+    #[test]
+    fn unknown_event_code_is_located() {
+        let errors = errors("[profiles.default.buttons]\ncross = \"BTN_NOPE\"\n");
+        assert_eq!((errors[0].line, errors[0].column), (2, 9));
+        assert!(errors[0].message.contains("unknown event code `BTN_NOPE`"));
+    }
+
+    #[test]
+    fn out_of_range_deadzone_is_located() {
+        let errors = errors("[profiles.default.right_stick.x]\ndeadzone = 200\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 12));
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.right_stick.x.deadzone: 200 is out of range"));
+    }
+
+    #[test]
+    fn non_monotone_curve_is_rejected() {
+        let errors = errors(
+            "[profiles.default.left_stick.y]\ncurve = { points = [[0.5, 0.5], [0.75, 0.25], [1.0, 1.0]] }\n",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+        assert!(errors[0].message.contains("not monotone at point 2"));
+    }
+
+    #[test]
+    fn out_of_range_curve_point_is_rejected() {
+        let errors = errors("[profiles.default.left_stick.y]\ncurve = { points = [[0.5, 1.5]] }\n");
+        assert!(errors[0].message.contains("within 0.0 and 1.0"));
+    }
+
+    #[test]
+    fn bezier_inputs_must_be_normalized() {
+        let errors =
+            errors("[profiles.default.left_stick.x]\ncurve = { bezier = [1.5, 0.0, 1.0, 0.5] }\n");
+        assert!(errors[0]
+            .message
+            .contains("inputs must be within 0.0 and 1.0"));
+    }
+
+    #[test]
+    fn out_of_range_threshold_is_located() {
+        let errors = errors(
+            "[profiles.default.map]
+r2_analog = { code = \"KEY_SPACE\", threshold = 1.5 }
+",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 47));
+        assert!(errors[0].message.contains("1.5 is out of range"));
+    }
+
+    #[test]
+    fn unknown_map_input_is_located() {
+        let errors = errors(
+            "[profiles.default.map]
+cross = \"KEY_A\"
+left_stick_in = \"KEY_B\"
+",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+        assert!(errors[0].message.contains("unknown input `left_stick_in`"));
+    }
+
+    #[test]
+    fn touchpad_sensitivity_must_be_positive() {
+        let errors =
+            errors("[profiles.default.touchpad]\nmode = \"trackpad\"\nsensitivity = 0.0\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (3, 15));
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.touchpad.sensitivity: 0 is out of range"));
+    }
+
+    #[test]
+    fn gyro_curve_is_validated() {
+        let errors =
+            errors("[profiles.default.gyro]\ncurve = { points = [[0.5, 0.8], [0.7, 0.6]] }\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.gyro.curve: "));
+    }
+
+    #[test]
+    fn gyro_stick_speed_must_be_positive() {
+        let errors = errors("[profiles.default.gyro]\nmode = \"stick\"\nstick_speed = -90.0\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.gyro.stick_speed: -90 is out of range"));
+    }
+
+    #[test]
+    fn flick_stick_threshold_must_be_a_fraction() {
+        let errors = errors("[profiles.default.flick_stick]\nthreshold = 1.5\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.flick_stick.threshold: 1.5 is out of range"));
+    }
+
+    #[test]
+    fn tilt_deadzone_must_be_below_max_angle() {
+        let errors = errors("[profiles.default.tilt]\nmax_angle = 30.0\ndeadzone = 40.0\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.tilt: deadzone 40 and max_angle 30 are out of range"));
+    }
+
+    #[test]
+    fn movement_thresholds_must_be_positive() {
+        let errors = errors("[profiles.default.movements]\ntap_threshold = 0.0\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .message
+            .starts_with("profiles.default.movements.tap_threshold: 0 is out of range"));
+    }
+
+    #[test]
+    fn touchpad_regions_must_exist() {
+        let errors = errors(
+            "[profiles.default.touchpad]\nregions = [[0.5, 0.0, 0.25, 1.0]]\n[profiles.default.map]\nregion2_click = \"BTN_START\"\n",
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 2);
+        assert!(errors[0].message.contains("region 1 is no rectangle"));
+        assert_eq!(errors[1].line, 4);
+        assert!(errors[1].message.contains("no touchpad region 2"));
+    }
+
+    #[test]
+    fn conflicting_bindings_are_reported_at_the_later_binding() {
+        let errors = errors("[profiles.default.buttons]\nsquare = \"BTN_EAST\"\n");
+        // circle keeps its default BTN_EAST, and isn't in the file to point at
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+        assert!(errors[0]
+            .message
+            .contains("BTN_EAST is already bound to `square`"));
+    }
+
+    #[test]
+    fn map_bindings_are_checked_for_conflicts() {
+        let conflicting = errors(
+            "[profiles.default.map]\nl2_analog = \"ABS_TILT_X\"\nleft_stick_up = \"ABS_RY\"\ncross = \"BTN_EAST\"\n",
+        );
+        assert_eq!(conflicting.len(), 3);
+        assert!(conflicting[0]
+            .message
+            .contains("map.cross: BTN_EAST is already bound to `circle`"));
+        assert!(conflicting[1]
+            .message
+            .contains("map.l2_analog: ABS_TILT_X is already bound to `left_x`"));
+        assert!(conflicting[2]
+            .message
+            .contains("map.left_stick_up: ABS_RY is already bound to `right_y`"));
+
+        // buttons & regions pushing an axis add to it, and replaced bindings are gone
+        assert!(check(
+            "[profiles.default.touchpad]\nregions = [[0.0, 0.0, 1.0, 1.0]]\n\
+             [profiles.default.map]\ncross = { code = \"ABS_RX\", value = 255 }\n\
+             region1_touch = { code = \"ABS_RX\", value = -255 }\ncircle = \"KEY_A\"\nsquare = \"BTN_EAST\"\n",
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn missing_default_profile_is_rejected() {
+        let errors = errors("default_profile = \"steam\"\n[profiles.retroarch]\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (1, 19));
+        assert!(errors[0].message.contains("no profile named `steam`"));
+    }
+
+    #[test]
+    fn problems_are_reported_per_profile() {
+        let errors =
+            errors("[profiles.a.right_stick]\nflat = -1\n[profiles.b.right_stick]\nflat = 200\n");
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].message.starts_with("profiles.a.right_stick.flat"));
+        assert!(errors[1].message.starts_with("profiles.b.right_stick.flat"));
+    }
+
+    #[test]
+    fn ambiguous_switch_chord_is_rejected() {
+        let errors = errors("[switch]\nhold = \"ps\"\nnext = \"ps\"\n");
+        assert_eq!(errors[0].line, 3);
+    }
+
+    #[test]
+    fn empty_hook_is_rejected() {
+        let errors = errors("[auto_switch]\nhook = \" \"\n");
+        assert_eq!((errors[0].line, errors[0].column), (2, 8));
+    }
+
+    #[test]
+    fn inherited_problems_are_reported_where_they_arise() {
+        let missing = errors(
+            "[profiles.base.buttons]\nsquare = \"BTN_EAST\"\n[profiles.game]\nextends = \"bsae\"\n",
+        );
+        assert_eq!(missing.len(), 1);
+        assert_eq!((missing[0].line, missing[0].column), (4, 11));
+        assert!(missing[0].message.contains("no profile named `bsae`"));
+
+        // a conflict only appearing once merged is reported at the extending profile
+        let merged = errors(
+            "[profiles.base.buttons]\nsquare = \"BTN_WEST\"\n[profiles.game]\nextends = \"base\"\n[profiles.game.buttons]\ncircle = \"BTN_WEST\"\n",
+        );
+        assert_eq!(merged.len(), 1);
+        assert!(merged[0]
+            .message
+            .starts_with("profiles.game.buttons.circle"));
+        assert_eq!(merged[0].line, 6);
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let errors =
+            errors("[profiles.default.left_stick]\nflat = 300\n[profiles.default.axes]\nright_y = \"ABS_RX\"\n");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[1].line, 4);
+    }
+}